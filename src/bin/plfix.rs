@@ -31,7 +31,7 @@ fn remove_playlist_duplicates(playlists: &mut Vec<Playlist>) -> usize {
         if playlist.name().starts_with("hist.") {
             continue;
         }
-        n_duplicates += playlist.remove_duplicates();
+        n_duplicates += playlist.dedup();
     }
     n_duplicates
 }
@@ -59,7 +59,7 @@ fn find_invalid_tracks<T: TracksFile, F: Fn(&Track) -> bool>(
     let mut invalid_count = 0usize;
     for file in files {
         let mut printed_header = false;
-        let it = file.tracks_unique().filter(|&x| !x.path.exists() && !ignore(x));
+        let it = file.tracks_unique().filter(|&x| !x.exists() && !ignore(x));
         for invalid_track in it {
             set.insert(invalid_track.clone());
             invalid_count += 1;
@@ -240,7 +240,7 @@ fn ask_resolve_invalid_paths(
                     match edit_method(track, &mut ans) {
                         Some(new_path) => {
                             println!("Path accepted.");
-                            println!("Old: '{}'", track.path);
+                            println!("Old: '{}'", track);
                             println!("New: '{}'", new_path);
                             edits.insert(track.clone(), new_path);
                         },
@@ -282,8 +282,8 @@ fn remove_tracks_from_playlists(
         // to the ignored meta-playlist.
         if playlist.name().starts_with("hist.") {
             for track in tracks.iter().filter(|&x| playlist.contains(x)) {
-                info!("Adding '{}' to ignore", track.path);
-                ignore_playlist.push(track.clone());
+                info!("Adding '{}' to ignore", track);
+                ignore_playlist.add_track(track.clone());
             }
             continue;
         }
@@ -291,7 +291,7 @@ fn remove_tracks_from_playlists(
         // Delete normally from all other playlists
         for track in tracks {
             if playlist.remove_all(track) > 0 {
-                info!("Deleting '{}' from {}", track.path, playlist.name());
+                info!("Deleting '{}' from {}", track, playlist.name());
             }
         }
     }
@@ -304,8 +304,8 @@ fn remove_tracks_from_playcounts(
 ) {
     for playcount in playcounts {
         for track in tracks.iter().filter(|&x| playcount.contains(x)) {
-            info!("Adding '{}' to ignore", track.path);
-            ignore_playlist.push(track.clone());
+            info!("Adding '{}' to ignore", track);
+            ignore_playlist.add_track(track.clone());
         }
     }
 }
@@ -351,8 +351,8 @@ fn main() -> ExitCode {
     println!("-- PLAYLISTS --");
 
     // Remove playlist duplicates
-    let mut playlists = match Playlist::iter() {
-        Some(it) => it.collect::<Vec<Playlist>>(),
+    let mut playlists = match Playlist::par_iter() {
+        Some(vec) => vec,
         None => return ExitCode::FAILURE,
     };
     match remove_playlist_duplicates(&mut playlists) {
@@ -377,8 +377,8 @@ fn main() -> ExitCode {
     println!("\n-- PLAYCOUNT --");
 
     // Remove playcount duplicates
-    let mut playcounts = match Playcount::iter() {
-        Some(it) => it.collect::<Vec<Playcount>>(),
+    let mut playcounts = match Playcount::par_iter() {
+        Some(vec) => vec,
         None => return ExitCode::FAILURE,
     };
     match merge_playcount_duplicates(&mut playcounts) {
@@ -426,7 +426,7 @@ fn main() -> ExitCode {
                     return ExitCode::FAILURE;
                 },
             };
-            println!("");
+            println!();
 
             // Remove tracks marked for deletion
             remove_tracks_from_playlists(&mut playlists, &deletes, &mut ignore_playlist);
@@ -440,10 +440,19 @@ fn main() -> ExitCode {
             }
 
             // Apply path edits
-            playlists.iter_mut().for_each(|x| { x.bulk_rename(&edits); });
-            playcounts.iter_mut().for_each(|x| { x.bulk_rename(&edits); });
+            // Not every file contains every renamed track, so missing ones are skipped
+            for playlist in playlists.iter_mut() {
+                if let Err(e) = playlist.rename_tracks(&edits, false) {
+                    error!("Failed to rename tracks in '{}': {}", playlist.path(), e);
+                }
+            }
+            for playcount in playcounts.iter_mut() {
+                if let Err(e) = playcount.rename_tracks(&edits, false) {
+                    error!("Failed to rename tracks in '{}': {}", playcount.path(), e);
+                }
+            }
             for (track, new_path) in edits {
-                info!("Renamed '{}' -> '{}'", track.path, new_path);
+                info!("Renamed '{}' -> '{}'", track, new_path);
             }
         }
     }
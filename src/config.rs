@@ -0,0 +1,114 @@
+use crate::music_dir;
+use camino::Utf8PathBuf;
+use log::warn;
+use serde::Deserialize;
+use std::fs;
+use std::sync::OnceLock;
+
+/// Optional settings read from the configuration file (see `Config::path()`).
+///
+/// Every path setting is resolved in the following order of precedence:
+/// 1. environment variable,
+/// 2. configuration file,
+/// 3. built-in default.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The music directory (env: `MUSIC_DIR`, default: `~/Music`).
+    pub music_dir: Option<String>,
+
+    /// The playlists directory (env: `MUSIC_PLAYLIST_DIR`, default: `MUSIC_DIR/Playlists`).
+    pub playlist_dir: Option<String>,
+
+    /// The playcounts directory (env: `MUSIC_PLAYCOUNT_DIR`, default: `MUSIC_DIR/.playcount`).
+    pub playcount_dir: Option<String>,
+}
+
+impl Config {
+    /// Returns the path to the configuration file, i.e. `~/.config/music-tools/config.toml`.
+    pub fn path() -> Option<Utf8PathBuf> {
+        let dir = dirs::config_dir()?;
+        let dir = Utf8PathBuf::from_path_buf(dir).ok()?;
+        Some(dir.join("music-tools").join("config.toml"))
+    }
+
+    /// Returns the configuration, reading it from the configuration file on first use.
+    /// If the file does not exist or is invalid, the default (empty) configuration is used.
+    pub fn get() -> &'static Config {
+        static CONFIG: OnceLock<Config> = OnceLock::new();
+        CONFIG.get_or_init(|| {
+            let path = match Self::path() {
+                Some(path) if path.is_file() => path,
+                _ => return Config::default(),
+            };
+            let contents = match fs::read_to_string(&path) {
+                Ok(str) => str,
+                Err(e) => {
+                    warn!("Failed to read config file '{}': {}, using defaults", path, e);
+                    return Config::default();
+                },
+            };
+            match toml::from_str(&contents) {
+                Ok(config) => config,
+                Err(e) => {
+                    warn!("Failed to parse config file '{}': {}, using defaults", path, e);
+                    Config::default()
+                },
+            }
+        })
+    }
+
+    /// Resolves the playlists directory.
+    pub fn playlist_dir() -> Utf8PathBuf {
+        resolve("MUSIC_PLAYLIST_DIR", &Self::get().playlist_dir, || music_dir().join("Playlists"))
+    }
+
+    /// Resolves the playcounts directory.
+    pub fn playcount_dir() -> Utf8PathBuf {
+        resolve("MUSIC_PLAYCOUNT_DIR", &Self::get().playcount_dir, || music_dir().join(".playcount"))
+    }
+}
+
+/// Resolves a path setting from an environment variable, a config file value or a default, in
+/// that order of precedence. Empty values are treated as unset.
+pub(crate) fn resolve<F: FnOnce() -> Utf8PathBuf>(env_var: &str, value: &Option<String>, default: F) -> Utf8PathBuf {
+    if let Ok(str) = std::env::var(env_var) {
+        if !str.is_empty() {
+            return Utf8PathBuf::from(str);
+        }
+    }
+    match value {
+        Some(str) if !str.is_empty() => Utf8PathBuf::from(str),
+        _ => default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn default() -> Utf8PathBuf {
+        Utf8PathBuf::from("/default")
+    }
+
+    // Each test uses its own variable, since tests run in parallel within the same process
+    #[test]
+    fn env_var_takes_precedence() {
+        std::env::set_var("MUSIC_TOOLS_TEST_ENV", "/env");
+        assert_eq!(resolve("MUSIC_TOOLS_TEST_ENV", &Some("/config".into()), default), "/env");
+        assert_eq!(resolve("MUSIC_TOOLS_TEST_ENV", &None, default), "/env");
+    }
+
+    #[test]
+    fn config_value_over_default() {
+        assert_eq!(resolve("MUSIC_TOOLS_TEST_UNSET", &Some("/config".into()), default), "/config");
+        assert_eq!(resolve("MUSIC_TOOLS_TEST_UNSET", &None, default), "/default");
+    }
+
+    #[test]
+    fn empty_values_are_unset() {
+        std::env::set_var("MUSIC_TOOLS_TEST_EMPTY", "");
+        assert_eq!(resolve("MUSIC_TOOLS_TEST_EMPTY", &Some("/config".into()), default), "/config");
+        assert_eq!(resolve("MUSIC_TOOLS_TEST_EMPTY", &Some(String::new()), default), "/default");
+    }
+}
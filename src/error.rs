@@ -0,0 +1,62 @@
+use crate::track::Track;
+use camino::Utf8PathBuf;
+use thiserror::Error;
+
+/// The error type for all fallible library operations.
+#[derive(Debug, Error)]
+pub enum MusicToolsError {
+    /// An I/O operation failed.
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+
+    /// Text could not be parsed. `line` is the line number (starting from 1), if known.
+    #[error("{}{reason}", line.map(|x| format!("line {}: ", x)).unwrap_or_default())]
+    Parse {
+        line: Option<usize>,
+        reason: String,
+    },
+
+    /// Tracks were expected to appear in a tracks file, but did not.
+    #[error("Tracks not found in '{path}': {}", quote_tracks(tracks))]
+    MissingTrack {
+        path: Utf8PathBuf,
+        tracks: Vec<Track>,
+    },
+
+    /// More plays of a track were to be subtracted than were recorded.
+    #[error("Cannot subtract {requested} plays of '{track}', only {available} recorded")]
+    NotEnoughPlays {
+        track: Track,
+        requested: usize,
+        available: usize,
+    },
+
+    /// A path could not be used, e.g. because it is not valid UTF-8 or lacks a filename.
+    #[error("{0}")]
+    InvalidPath(String),
+
+    /// An ID3 tag could not be read.
+    #[error("{0}")]
+    Id3(#[from] id3::Error),
+
+    /// An object's internal state is inconsistent.
+    #[error("{0}")]
+    Integrity(String),
+}
+
+/// A `Result` alias with `MusicToolsError` as the error type.
+pub type Result<T> = std::result::Result<T, MusicToolsError>;
+
+impl MusicToolsError {
+    /// Creates a `Parse` error without a line number.
+    pub fn parse<T: Into<String>>(reason: T) -> Self {
+        MusicToolsError::Parse { line: None, reason: reason.into() }
+    }
+}
+
+fn quote_tracks(tracks: &[Track]) -> String {
+    tracks.iter()
+        .map(|x| format!("'{}'", x))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
@@ -1,26 +1,37 @@
 pub mod track;
 pub mod playlist;
 pub mod playcount;
+pub mod memtracks;
+pub mod config;
+pub mod error;
 
 mod tracksfile;
 
-use anyhow::{anyhow, Result};
+use crate::error::{MusicToolsError, Result};
 use camino::{Utf8Path, Utf8PathBuf};
 use log::warn;
-use std::fs;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
 use std::path::Path;
 use std::sync::OnceLock;
 
 /// Returns the path to the music directory.
+/// This is the value of the `MUSIC_DIR` environment variable if set, then the `music_dir` setting
+/// from the config file (see `config::Config`), or `~/Music` otherwise.
 pub fn music_dir() -> &'static Utf8Path {
     static MUSIC_DIR: OnceLock<Utf8PathBuf> = OnceLock::new();
-    MUSIC_DIR.get_or_init(|| path_from(dirs::home_dir, "Music"))
+    MUSIC_DIR.get_or_init(|| config::resolve(
+        "MUSIC_DIR",
+        &config::Config::get().music_dir,
+        || path_from(dirs::home_dir, "Music"),
+    ))
 }
 
 /// Constructs a path by concatenating a `dirs::*` function output and an arbitrary relative path.
 ///
 /// # Examples
-/// ```
+/// ```no_run
+/// # use music_tools::path_from;
 /// assert_eq!(path_from(dirs::home_dir, "my_file.txt"), "/home/user/my_file.txt");
 /// ```
 pub fn path_from<A: AsRef<Path>, B: AsRef<Path>>(base_dir: fn() -> Option<A>, rel_path: B) -> Utf8PathBuf {
@@ -43,6 +54,7 @@ pub fn path_from<A: AsRef<Path>, B: AsRef<Path>>(base_dir: fn() -> Option<A>, re
 }
 
 /// Returns an iterator over directory files, with a filtering function.
+/// The paths are sorted, so that the order is stable across runs and machines.
 fn iter_paths<F: Fn(&Utf8Path) -> bool>(dir: &Utf8Path, f: F) -> Result<impl Iterator<Item = Utf8PathBuf>> {
     let mut path_strings = Vec::<Utf8PathBuf>::new();
     for result in fs::read_dir(dir)? {
@@ -56,12 +68,84 @@ fn iter_paths<F: Fn(&Utf8Path) -> bool>(dir: &Utf8Path, f: F) -> Result<impl Ite
         let path = entry.path();
         let path_str = match path.to_str() {
             Some(str) => str,
-            None => return Err(anyhow!("Failed to convert system path {:?} to UTF-8 (other encodings not supported)", path)),
+            None => return Err(MusicToolsError::InvalidPath(
+                format!("Failed to convert system path {:?} to UTF-8 (other encodings not supported)", path)
+            )),
         };
         let path = Utf8PathBuf::from(path_str);
         if f(&path) {
             path_strings.push(path);
         }
     }
+    path_strings.sort_unstable();
     Ok(path_strings.into_iter())
 }
+
+/// Writes a file atomically, by first writing to a temporary file in the same directory and then
+/// renaming it over the target path. If anything fails, the target file is left intact.
+/// Symlinks are followed, i.e. the file they point to is replaced, and an existing file's
+/// permissions are kept.
+fn write_atomic<F: FnOnce(&mut BufWriter<File>) -> std::io::Result<()>>(path: &Utf8Path, f: F) -> Result<()> {
+    let path = match fs::canonicalize(path) {
+        Ok(real_path) => match Utf8PathBuf::from_path_buf(real_path) {
+            Ok(real_path) => real_path,
+            Err(e) => return Err(MusicToolsError::InvalidPath(format!("Path '{:?}' is not valid UTF-8", e))),
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => path.to_path_buf(),
+        Err(e) => return Err(e.into()),
+    };
+    let permissions = fs::metadata(&path).ok().map(|x| x.permissions());
+    let file_name = match path.file_name() {
+        Some(name) => name,
+        None => return Err(MusicToolsError::InvalidPath(format!("Failed to extract filename from '{}'", path))),
+    };
+    let tmp_path = path.with_file_name(format!(".{}.tmp", file_name));
+    let result = File::create(&tmp_path)
+        .and_then(|file| {
+            if let Some(permissions) = permissions {
+                file.set_permissions(permissions)?;
+            }
+            let mut writer = BufWriter::new(file);
+            f(&mut writer)?;
+            writer.flush()?;
+            writer.get_ref().sync_all()
+        })
+        .and_then(|_| fs::rename(&tmp_path, &path));
+    if let Err(e) = result {
+        if tmp_path.exists() {
+            if let Err(e) = fs::remove_file(&tmp_path) {
+                warn!("Failed to remove temporary file '{}': {}", tmp_path, e);
+            }
+        }
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Quotes a CSV field according to RFC 4180, if necessary.
+fn csv_quote(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Converts a string to a quoted JSON string literal.
+fn json_quote(str: &str) -> String {
+    let mut quoted = String::with_capacity(str.len() + 2);
+    quoted.push('"');
+    for c in str.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,220 @@
+pub use crate::tracksfile::{PathMapping, TracksFile, TracksFileSummary};
+
+use crate::track::Track;
+use crate::playlist::{Playlist, SortKey};
+use crate::error::{MusicToolsError, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use log::warn;
+use std::collections::HashMap;
+use std::io;
+
+/// An in-memory list of tracks, not backed by any file.
+///
+/// This is useful for composing operations (e.g. set algebra, dedup, sorting) without touching the
+/// disk, and then dumping the result to a real playlist (see `to_playlist()`). It also serves as
+/// a minimal reference implementation of `TracksFile`.
+///
+/// The path given to `new()` is nominal only. Since there is no file, `open()` and `write()`
+/// always fail, and `iter()` yields nothing.
+#[derive(Debug)]
+pub struct MemTracks {
+    path: Utf8PathBuf,
+    tracks: Vec<Track>,
+
+    /// Cached index for `tracks`, to avoid linear search.
+    tracks_map: HashMap<Track, Vec<usize>>,
+
+    /// Whether the list was modified since it was created.
+    is_modified: bool,
+}
+
+impl MemTracks {
+    /// Creates a new list tied to a nominal path, containing the given tracks in order.
+    pub fn from_tracks<T: AsRef<Utf8Path>, I: IntoIterator<Item = Track>>(fpath: T, tracks: I) -> Self {
+        let mut mem = Self {
+            path: Utf8PathBuf::from(fpath.as_ref()),
+            tracks: tracks.into_iter().collect(),
+            tracks_map: HashMap::new(),
+            is_modified: false,
+        };
+        mem.rebuild_tracks_map();
+        mem
+    }
+
+    /// Appends a new track to the end of the list.
+    pub fn add_track(&mut self, track: Track) {
+        crate::tracksfile::index_track(&mut self.tracks_map, &track, self.tracks.len());
+        self.tracks.push(track);
+        self.is_modified = true;
+        debug_assert!(self.verify_integrity());
+    }
+
+    /// Sorts the tracks by a given key. The sort is stable, i.e. tracks with equal keys keep their
+    /// relative order.
+    pub fn sort(&mut self, key: SortKey) {
+        match key {
+            SortKey::Path => self.tracks.sort_by(|a, b| a.path.cmp(&b.path)),
+            SortKey::FileName => self.tracks.sort_by(|a, b| a.path.file_name().cmp(&b.path.file_name())),
+        }
+        self.rebuild_tracks_map();
+        self.is_modified = true;
+    }
+
+    /// Creates a new playlist tied to `fpath`, containing all tracks in order.
+    /// The playlist is not written to disk.
+    pub fn to_playlist<T: AsRef<Utf8Path>>(&self, fpath: T) -> Result<Playlist> {
+        let mut playlist = Playlist::new(fpath)?;
+        for track in &self.tracks {
+            playlist.add_track(track.clone());
+        }
+        Ok(playlist)
+    }
+
+    /// Rebuilds `tracks_map` from scratch, based on `tracks`.
+    fn rebuild_tracks_map(&mut self) {
+        self.tracks_map.clear();
+        for (i, track) in self.tracks.iter().enumerate() {
+            crate::tracksfile::index_track(&mut self.tracks_map, track, i);
+        }
+        debug_assert!(self.verify_integrity());
+    }
+
+    /// Verifies the integrity of the struct. This is quite slow and intended for use with
+    /// `debug_assert`.
+    fn verify_integrity(&self) -> bool {
+        for (i, track) in self.tracks.iter().enumerate() {
+            if !self.tracks_map.get(track).is_some_and(|x| x.contains(&i)) {
+                return false;
+            }
+        }
+        for (track, indices) in self.tracks_map.iter() {
+            if indices.is_empty() || indices.iter().any(|&i| &self.tracks[i] != track) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl TracksFile for MemTracks {
+    fn open<T: AsRef<Utf8Path>>(fpath: T) -> Result<Self> {
+        Err(MusicToolsError::Io(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("In-memory track lists cannot be opened from a file ('{}')", fpath.as_ref()),
+        )))
+    }
+
+    fn new<T: AsRef<Utf8Path>>(fpath: T) -> Result<Self> {
+        Ok(Self::from_tracks(fpath, []))
+    }
+
+    fn open_or_new<T: AsRef<Utf8Path>>(fpath: T) -> Result<Self> {
+        Self::new(fpath)
+    }
+
+    fn iter() -> Option<impl Iterator<Item = Self>> {
+        None::<std::iter::Empty<Self>>
+    }
+
+    fn path(&self) -> &Utf8PathBuf {
+        &self.path
+    }
+
+    fn tracks(&self) -> impl Iterator<Item = &Track> {
+        self.tracks.iter()
+    }
+
+    fn tracks_unique(&self) -> impl Iterator<Item = &Track> {
+        self.tracks_map.keys()
+    }
+
+    fn contains(&self, track: &Track) -> bool {
+        self.tracks_map.contains_key(track)
+    }
+
+    fn track_positions(&self, track: &Track) -> Option<&Vec<usize>> {
+        self.tracks_map.get(track)
+    }
+
+    fn is_modified(&self) -> bool {
+        self.is_modified
+    }
+
+    fn write(&mut self) -> Result<()> {
+        Err(MusicToolsError::Io(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("In-memory track lists cannot be written ('{}'), see to_playlist()", self.path),
+        )))
+    }
+
+    fn remove_at(&mut self, index: usize) {
+        if index >= self.tracks.len() {
+            warn!("Out-of-bounds remove_at requested (index: {}, len: {})", index, self.tracks.len());
+            return;
+        }
+        self.tracks.remove(index);
+        self.rebuild_tracks_map();
+        self.is_modified = true;
+    }
+
+    fn swap_remove_at(&mut self, index: usize) {
+        if index >= self.tracks.len() {
+            warn!("Out-of-bounds swap_remove_at requested (index: {}, len: {})", index, self.tracks.len());
+            return;
+        }
+        self.tracks.swap_remove(index);
+        self.rebuild_tracks_map();
+        self.is_modified = true;
+    }
+
+    fn remove_many(&mut self, indices: &[usize]) -> usize {
+        let mut is_removed = vec![false; self.tracks.len()];
+        let mut n_removed = 0usize;
+        for &index in indices {
+            if index >= self.tracks.len() {
+                warn!("Out-of-bounds remove_many requested (index: {}, len: {})", index, self.tracks.len());
+                continue;
+            }
+            if !is_removed[index] {
+                is_removed[index] = true;
+                n_removed += 1;
+            }
+        }
+        if n_removed == 0 {
+            return 0;
+        }
+
+        let tracks = std::mem::take(&mut self.tracks);
+        self.tracks = tracks.into_iter()
+            .zip(is_removed)
+            .filter_map(|(track, is_removed)| (!is_removed).then_some(track))
+            .collect();
+        self.rebuild_tracks_map();
+        self.is_modified = true;
+        n_removed
+    }
+
+    fn remove_all(&mut self, track: &Track) -> usize {
+        if !self.tracks_map.contains_key(track) {
+            return 0;
+        }
+        let indices = self.tracks_map[track].clone();
+        self.remove_many(&indices)
+    }
+
+    fn bulk_rename(&mut self, edits: &HashMap<Track, Utf8PathBuf>) -> usize {
+        let mut n_changed = 0usize;
+        for (target_track, new_path) in edits {
+            if !self.tracks_map.contains_key(target_track) {
+                continue;
+            }
+            for &index in &self.tracks_map[target_track] {
+                self.tracks[index].path = new_path.clone();
+                n_changed += 1;
+            }
+            self.is_modified = true;
+        }
+        self.rebuild_tracks_map();
+        n_changed
+    }
+}
@@ -1,9 +1,18 @@
 use crate::track::Track;
 use camino::{Utf8Path, Utf8PathBuf};
-use anyhow::{anyhow, Result, Error};
+use crate::error::{MusicToolsError, Result};
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use std::num::IntErrorKind;
+use std::ops::{Deref, DerefMut};
+
+/// The smallest Unix timestamp accepted in the timestamp column, i.e. the smallest one with 10
+/// digits (2001-09-09T01:46:40Z).
+const MIN_UNIX_TIMESTAMP: i64 = 1_000_000_000;
 
 /// Representation of a single line in a playcount file.
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+///
+/// Equality and hashing only consider the entry's contents, not where it was read from.
+#[derive(Debug, Clone)]
 pub struct Entry {
     /// The track that was played.
     pub track: Track,
@@ -13,6 +22,18 @@ pub struct Entry {
     /// playcount file, because multiple entries for the same `track` may exist, in which case
     /// all their `count`s should be summed up.
     pub count: usize,
+
+    /// When `track` was played, if known. This corresponds to an optional third column in the
+    /// playcount file, which can hold either a Unix timestamp or an ISO-8601 date/datetime.
+    /// When written back, the original text of the column is kept, unless the timestamp was
+    /// changed, in which case it is formatted anew (see `format_timestamp()`).
+    pub timestamp: Option<DateTime<Utc>>,
+
+    /// The original text of the timestamp column, if the entry was read from a file.
+    pub(super) timestamp_str: Option<String>,
+
+    /// The line number (starting from 1) the entry was parsed from, if it was read from a file.
+    pub(super) line: Option<usize>,
 }
 
 impl Entry {
@@ -20,29 +41,155 @@ impl Entry {
         Entry {
             track: Track::new(fpath),
             count,
+            timestamp: None,
+            timestamp_str: None,
+            line: None,
         }
     }
+
+    /// Returns the text of the timestamp column to write, if any (see `timestamp`).
+    pub(super) fn timestamp_column(&self) -> Option<String> {
+        let ts = self.timestamp?;
+        match &self.timestamp_str {
+            Some(str) if Self::parse_timestamp(str).is_ok_and(|x| x == ts) => Some(str.clone()),
+            _ => Some(Self::format_timestamp(ts)),
+        }
+    }
+
+    /// Formats a timestamp for the timestamp column. The Unix format is used, unless the
+    /// timestamp has fewer than 10 digits in it (i.e. it predates September 2001), in which case
+    /// RFC 3339 is used, so that it is not mistaken for part of the path (see `parse_timestamp()`).
+    pub(super) fn format_timestamp(ts: DateTime<Utc>) -> String {
+        if ts.timestamp() >= MIN_UNIX_TIMESTAMP {
+            ts.timestamp().to_string()
+        } else {
+            ts.to_rfc3339_opts(SecondsFormat::Secs, true)
+        }
+    }
+
+    /// Returns the line number (starting from 1) in the playcount file the entry was read from.
+    /// Entries created programmatically have no line number. Line numbers are not updated when
+    /// the playcount is modified or written.
+    pub fn line(&self) -> Option<usize> {
+        self.line
+    }
+
+    /// Parses a timestamp column, which can be either a Unix timestamp, an RFC 3339 datetime or
+    /// a plain ISO-8601 date (interpreted as midnight UTC).
+    ///
+    /// Since paths may contain tabs, a path like "Artist\t1999" would be ambiguous. To keep such
+    /// paths readable, Unix timestamps must consist of at least 10 digits, i.e. shorter numbers
+    /// are not considered timestamps.
+    fn parse_timestamp(str: &str) -> Result<DateTime<Utc>> {
+        if str.bytes().all(|x| x.is_ascii_digit()) && !str.is_empty() {
+            let secs = match str.parse::<i64>() {
+                Ok(secs) if secs >= MIN_UNIX_TIMESTAMP => secs,
+                Ok(_) => return Err(MusicToolsError::parse(format!("Unix timestamp '{}' must have at least 10 digits", str))),
+                Err(_) => return Err(MusicToolsError::parse(format!("Unix timestamp '{}' is out of range", str))),
+            };
+            return match DateTime::from_timestamp(secs, 0) {
+                Some(datetime) => Ok(datetime),
+                None => Err(MusicToolsError::parse(format!("Unix timestamp '{}' is out of range", str))),
+            };
+        }
+        if let Ok(datetime) = DateTime::parse_from_rfc3339(str) {
+            return Ok(datetime.with_timezone(&Utc));
+        }
+        match NaiveDate::parse_from_str(str, "%Y-%m-%d") {
+            Ok(date) => Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc()),
+            Err(e) => Err(MusicToolsError::parse(format!("Failed to parse timestamp '{}': {}", str, e))),
+        }
+    }
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.track == other.track && self.count == other.count && self.timestamp == other.timestamp
+    }
+}
+
+impl Eq for Entry {}
+
+impl std::hash::Hash for Entry {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.track.hash(state);
+        self.count.hash(state);
+        self.timestamp.hash(state);
+    }
+}
+
+/// Mutable access to an entry's count, with read-only access to the rest of the entry.
+///
+/// Mutating `track` directly would desynchronize the owning playcount's internal index, so this
+/// wrapper only derefs to `count`.
+#[derive(Debug)]
+pub struct EntryMut<'a> {
+    entry: &'a mut Entry,
+}
+
+impl<'a> EntryMut<'a> {
+    pub(super) fn new(entry: &'a mut Entry) -> Self {
+        EntryMut { entry }
+    }
+
+    /// Returns the track that was played.
+    pub fn track(&self) -> &Track {
+        &self.entry.track
+    }
+
+    /// Returns when the track was played, if known.
+    pub fn timestamp(&self) -> Option<DateTime<Utc>> {
+        self.entry.timestamp
+    }
+}
+
+impl Deref for EntryMut<'_> {
+    type Target = usize;
+
+    fn deref(&self) -> &usize {
+        &self.entry.count
+    }
+}
+
+impl DerefMut for EntryMut<'_> {
+    fn deref_mut(&mut self) -> &mut usize {
+        &mut self.entry.count
+    }
 }
 
 impl std::str::FromStr for Entry {
-    type Err = Error;
+    type Err = MusicToolsError;
 
     fn from_str(line: &str) -> Result<Self> {
-        let mut it = line.splitn(2, '\t');
-        let count_str = match it.next() {
+        let (count_str, rest) = match line.split_once('\t') {
             Some(split) => split,
-            None => return Err(anyhow!("Failed to extract count substring from playcount line '{}'", line)),
+            None => return Err(MusicToolsError::parse(format!("Failed to extract path substring from playcount line '{}'", line))),
         };
-        let path = match it.next() {
-            Some(split) => Utf8PathBuf::from(split),
-            None => return Err(anyhow!("Failed to extract path substring from playcount line '{}'", line)),
+
+        // Paths may contain tabs, so the last column is only taken to be a timestamp if it parses
+        // as one
+        let (path, timestamp) = match rest.rsplit_once('\t') {
+            Some((path, ts_str)) => match Self::parse_timestamp(ts_str) {
+                Ok(ts) => (path, Some((ts, ts_str))),
+                Err(_) => (rest, None),
+            },
+            None => (rest, None),
         };
 
         let count = match count_str.parse::<usize>() {
             Ok(num) => num,
-            Err(e) => return Err(anyhow!("Failed to convert count substring '{}' to number: {}", count_str, e)),
+            Err(_) if count_str.starts_with('-') && count_str[1..].parse::<usize>().is_ok() =>
+                return Err(MusicToolsError::parse(format!("Count '{}' must not be negative", count_str))),
+            Err(e) if *e.kind() == IntErrorKind::PosOverflow =>
+                return Err(MusicToolsError::parse(format!("Count '{}' is too large (maximum is {})", count_str, usize::MAX))),
+            Err(e) => return Err(MusicToolsError::parse(format!("Failed to convert count substring '{}' to number: {}", count_str, e))),
         };
 
-        Ok(Entry::new(path, count))
+        let mut entry = Entry::new(Utf8PathBuf::from(path), count);
+        if let Some((ts, ts_str)) = timestamp {
+            entry.timestamp = Some(ts);
+            entry.timestamp_str = Some(ts_str.to_string());
+        }
+        Ok(entry)
     }
 }
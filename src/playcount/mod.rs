@@ -1,18 +1,43 @@
 pub mod entry;
+pub mod stats;
 
-pub use entry::Entry;
-pub use crate::tracksfile::TracksFile;
+pub use entry::{Entry, EntryMut};
+pub use stats::PlaycountStats;
+pub use crate::tracksfile::{PathMapping, TracksFile, TracksFileSummary};
 
-use crate::music_dir;
+use crate::config::Config;
+use crate::playlist::Playlist;
 use crate::track::Track;
-use anyhow::{anyhow, Result};
+use crate::error::{MusicToolsError, Result};
 use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, Utc};
 use log::{error, warn};
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::{Write, BufRead, BufReader};
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{Write, BufRead, BufReader, Read, Seek, SeekFrom};
 use std::sync::OnceLock;
 
+/// The minimum `Track::similarity()` score for `Playcount::suggest_repaths()` to propose a
+/// candidate.
+pub const REPATH_THRESHOLD: f32 = 0.75;
+
+/// Strategies for reconciling two copies of a playcount (see `Playcount::sync()`).
+/// All strategies compare per-track total counts, and copy the entries of tracks missing locally
+/// (timestamps included).
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SyncStrategy {
+    /// Take the higher of the two totals. Suitable when both copies may have recorded the same
+    /// plays, e.g. files synced between devices.
+    Max,
+
+    /// Add the remote total to the local one. Suitable when the copies recorded disjoint plays.
+    Sum,
+
+    /// Keep local totals as they are, only adding tracks which are missing locally.
+    PreferLocal,
+}
+
 #[derive(Debug)]
 pub struct Playcount {
     path: Utf8PathBuf,
@@ -29,7 +54,95 @@ impl Playcount {
     /// Returns the path to the playcount directory.
     fn playcount_dir() -> &'static Utf8Path {
         static PLAYCOUNTS_DIR: OnceLock<Utf8PathBuf> = OnceLock::new();
-        PLAYCOUNTS_DIR.get_or_init(|| music_dir().join(".playcount"))
+        PLAYCOUNTS_DIR.get_or_init(Config::playcount_dir)
+    }
+
+    /// Works like `open()`, but instead of skipping lines which fail to parse, returns an error
+    /// listing all of their line numbers.
+    pub fn open_strict<T: AsRef<Utf8Path>>(fpath: T) -> Result<Self> {
+        let mut bad_lines = Vec::<String>::new();
+        let pc = Self::open_with(fpath, |_, n, _, e| bad_lines.push(format!("line {}: {}", n, e)))?;
+        if !bad_lines.is_empty() {
+            return Err(MusicToolsError::parse(
+                format!("Failed to parse {} lines in '{}': {}", bad_lines.len(), pc.path, bad_lines.join("; "))
+            ));
+        }
+        Ok(pc)
+    }
+
+    /// Works like `open()`, but additionally returns all lines which failed to parse and were
+    /// skipped, alongside their line numbers (starting from 1).
+    pub fn open_lossy<T: AsRef<Utf8Path>>(fpath: T) -> Result<(Self, Vec<(usize, String)>)> {
+        let mut bad_lines = Vec::<(usize, String)>::new();
+        let pc = Self::open_with(fpath, |_, n, line, _| bad_lines.push((n, line.to_string())))?;
+        Ok((pc, bad_lines))
+    }
+
+    /// Creates a new object from existing file contents. Lines which fail to parse are skipped,
+    /// and passed to `on_error` alongside the file path, their line number (starting from 1) and
+    /// the parsing error.
+    fn open_with<T, F>(fpath: T, mut on_error: F) -> Result<Self>
+    where
+        T: AsRef<Utf8Path>,
+        F: FnMut(&Utf8Path, usize, &str, MusicToolsError),
+    {
+        let mut pc = Self::new(fpath)?;
+
+        let file = BufReader::new(File::open(&pc.path)?);
+        for (i, line) in file.lines().enumerate() {
+            let line = line?;
+            let entry = match line.parse::<Entry>() {
+                Ok(entry) => Entry { line: Some(i + 1), ..entry },
+                Err(e) => {
+                    on_error(&pc.path, i + 1, &line, e);
+                    continue;
+                },
+            };
+            pc.push_entry(entry);
+        }
+        debug_assert!(pc.verify_integrity());
+        Ok(pc)
+    }
+
+    /// Works like `iter()`, but opens all files in parallel and returns them at once, in the same
+    /// order as `iter()` would.
+    pub fn par_iter() -> Option<Vec<Self>> {
+        let paths = match Self::iter_paths() {
+            Ok(it) => it.collect::<Vec<Utf8PathBuf>>(),
+            Err(e) => {
+                error!("Failed to list the playcounts directory '{:?}': {}", Self::playcount_dir(), e);
+                return None;
+            },
+        };
+        let playcounts = paths.into_par_iter()
+            .filter_map(|path|
+                match Self::open(&path) {
+                    Ok(playcount) => Some(playcount),
+                    Err(e) => {
+                        warn!("Failed to read playcount '{:?}': {}, skipping", path, e);
+                        None
+                    },
+                }
+            )
+            .collect();
+        Some(playcounts)
+    }
+
+    /// Folds all playcount files into a single playcount, with one entry per track and timestamp
+    /// holding the total number of plays (see `merge_from()`). Tracks are ordered by first appearance, going
+    /// through the files in the same order as `iter()`. Files which fail to open are skipped.
+    ///
+    /// The result is tied to the playcounts directory itself, so that it cannot be accidentally
+    /// written over one of the files. Nothing is written to disk.
+    pub fn aggregate_all() -> Result<Self> {
+        let mut all = Self::new(Self::playcount_dir())?;
+        for path in Self::iter_paths()? {
+            match Self::open(&path) {
+                Ok(playcount) => all.merge_from(&playcount),
+                Err(e) => warn!("Failed to read playcount '{:?}': {}, skipping", path, e),
+            }
+        }
+        Ok(all)
     }
 
     /// Returns an iterator over all playcount file paths.
@@ -44,16 +157,18 @@ impl Playcount {
     fn rebuild_tracks_map(&mut self) {
         self.tracks_map.clear();
         for (i, entry) in self.entries.iter().enumerate() {
-            let track = &entry.track;
-            if self.tracks_map.contains_key(track) {
-                self.tracks_map.get_mut(track).unwrap().push(i);
-            } else {
-                self.tracks_map.insert(track.clone(), vec![i]);
-            }
+            crate::tracksfile::index_track(&mut self.tracks_map, &entry.track, i);
         }
         debug_assert!(self.verify_integrity());
     }
 
+    /// Appends an entry to `entries`, keeping `tracks_map` in sync. This does not mark the
+    /// playcount as modified.
+    fn push_entry(&mut self, entry: Entry) {
+        crate::tracksfile::index_track(&mut self.tracks_map, &entry.track, self.entries.len());
+        self.entries.push(entry);
+    }
+
     /// Verifies the integrity of the struct. This is quite slow and intended for use with
     /// `debug_assert`.
     fn verify_integrity(&self) -> bool {
@@ -83,73 +198,349 @@ impl Playcount {
         self.entries.iter()
     }
 
-    /// Merges entries corresponding to the same track by keeping only the first one and
-    /// incrementing its count by the sum of the repeated ones (which are removed).
+    /// Returns an iterator to all entries in the playcount, in order of appearance, allowing
+    /// their counts to be modified in place.
+    ///
+    /// Only `count` can be mutated, as changing an entry's track would invalidate the internal
+    /// track index. Since there is no way to tell whether any count was actually changed, calling
+    /// this method always marks the playcount as modified.
+    pub fn entries_mut(&mut self) -> impl Iterator<Item = EntryMut<'_>> {
+        self.is_modified = true;
+        self.entries.iter_mut().map(EntryMut::new)
+    }
+
+    /// Merges entries corresponding to the same track and timestamp by keeping only the first one
+    /// and incrementing its count by the sum of the repeated ones (which are removed). Entries with
+    /// different timestamps are kept apart, so that `plays_since()` is unaffected.
     /// Returns the number of duplicate entries that were removed.
     pub fn merge_duplicates(&mut self) -> usize {
-        // Maps self.entries indices to the amounts they should be incremented by.
-        let mut increments = HashMap::<usize, usize>::new();
-
-        // A list of all indices to remove from self.entries
-        let mut dupe_indices = Vec::new();
-
-        for track in self.tracks_unique() {
-            if let Some(pos) = self.track_positions(track) {
-                if pos.len() > 1 {
-                    dupe_indices.extend_from_slice(&pos[1..]);
-                    increments.insert(
-                        pos[0],
-                        pos[1..].iter().map(|&x| self.entries[x].count).sum(),
-                    );
+        let mut firsts = HashMap::<(&Track, Option<DateTime<Utc>>), usize>::new();
+        let mut dupes = Vec::<(usize, usize)>::new();
+        for (i, entry) in self.entries.iter().enumerate() {
+            match firsts.get(&(&entry.track, entry.timestamp)) {
+                Some(&first) => dupes.push((first, i)),
+                None => { firsts.insert((&entry.track, entry.timestamp), i); },
+            }
+        }
+        for &(first, dupe) in &dupes {
+            self.entries[first].count += self.entries[dupe].count;
+        }
+        let indices = dupes.into_iter()
+            .map(|(_, dupe)| dupe)
+            .collect::<Vec<usize>>();
+        self.remove_many(&indices)
+    }
+
+    /// Adds the count of an entry to the first entry of the same track and timestamp, or appends
+    /// a copy of it if there is none. Entries with a count of 0 are ignored.
+    fn merge_entry(&mut self, entry: &Entry) {
+        if entry.count == 0 {
+            return;
+        }
+        let index = self.tracks_map.get(&entry.track)
+            .and_then(|x| x.iter().find(|&&i| self.entries[i].timestamp == entry.timestamp));
+        match index {
+            Some(&index) => self.entries[index].count += entry.count,
+            None => self.push_entry(Entry { line: None, ..entry.clone() }),
+        }
+        self.is_modified = true;
+        debug_assert!(self.verify_integrity());
+    }
+
+    /// Reorders entries by their count, in ascending or descending order.
+    /// The sort is stable, i.e. entries with equal counts retain their relative order.
+    pub fn sort_by_count(&mut self, descending: bool) {
+        if descending {
+            self.entries.sort_by_key(|x| std::cmp::Reverse(x.count));
+        } else {
+            self.entries.sort_by_key(|x| x.count);
+        }
+        self.rebuild_tracks_map();
+        self.is_modified = true;
+    }
+
+    /// Reconciles this playcount with another copy of it, according to a strategy. Tracks are
+    /// processed in order of their first appearance in `remote`. Remote entries are merged in as
+    /// in `merge_from()`, except for `SyncStrategy::Max`, which records the difference of totals
+    /// of tracks present in both as untimed plays (see `add_play()`).
+    /// The playcount is only marked as modified if any count changed.
+    pub fn sync(&mut self, remote: &Playcount, strategy: SyncStrategy) {
+        let mut seen = HashSet::new();
+        for entry in &remote.entries {
+            let track = &entry.track;
+            if !seen.insert(track) {
+                continue;
+            }
+            if strategy == SyncStrategy::Sum || !self.contains(track) {
+                for &i in &remote.tracks_map[track] {
+                    self.merge_entry(&remote.entries[i]);
                 }
+            } else if strategy == SyncStrategy::Max {
+                let increment = remote.plays_for(track).saturating_sub(self.plays_for(track));
+                self.add_play(track, increment);
             }
         }
+    }
 
-        let n_duplicates = dupe_indices.len();
+    /// Returns the total number of plays, i.e. the sum of all entries' counts.
+    pub fn total_plays(&self) -> usize {
+        self.entries.iter().map(|x| x.count).sum()
+    }
 
-        // Tally up count and remove duplicates
-        if n_duplicates != 0 {
-            increments.into_iter().for_each(|(index, incr)| self.entries[index].count += incr);
-            dupe_indices.sort_unstable();
-            dupe_indices.into_iter().rev().for_each(|x| self.remove_at(x));
-            self.is_modified = true;
+    /// Returns the total number of plays of a track, summed across all its entries.
+    pub fn plays_for(&self, track: &Track) -> usize {
+        match self.track_positions(track) {
+            Some(pos) => pos.iter().map(|&i| self.entries[i].count).sum(),
+            None => 0,
+        }
+    }
+
+    /// Returns an iterator over all unique tracks, each with its total number of plays summed
+    /// across all its entries. The order is undefined and arbitrary, like in `tracks_unique()`.
+    pub fn totals(&self) -> impl Iterator<Item = (&Track, usize)> {
+        self.tracks_map.iter()
+            .map(|(track, pos)| (track, pos.iter().map(|&i| self.entries[i].count).sum()))
+    }
+
+    /// Returns the total number of plays recorded at or after `ts`.
+    /// Entries without a timestamp are not counted.
+    pub fn plays_since(&self, ts: DateTime<Utc>) -> usize {
+        self.entries.iter()
+            .filter(|x| x.timestamp.is_some_and(|y| y >= ts))
+            .map(|x| x.count)
+            .sum()
+    }
+
+    /// Proposes new locations for tracks which do not exist on disk, by picking the most similar
+    /// of the `candidates` (see `Track::similarity()`), e.g. a listing of the music directory.
+    /// Tracks with no candidate scoring at least `REPATH_THRESHOLD` are omitted.
+    ///
+    /// The suggestions are advisory only; they can be applied with `rename_tracks()`.
+    pub fn suggest_repaths(&self, candidates: &[Track]) -> HashMap<Track, Track> {
+        let mut suggestions = HashMap::new();
+        for track in self.tracks_unique().filter(|x| !x.exists()) {
+            let best = candidates.iter()
+                .filter(|&x| x != track)
+                .map(|x| (x, track.similarity(x)))
+                .filter(|(_, score)| *score >= REPATH_THRESHOLD)
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+            if let Some((candidate, _)) = best {
+                suggestions.insert(track.clone(), candidate.clone());
+            }
         }
+        suggestions
+    }
+
+    /// Returns all entries whose tracks do not exist on disk.
+    pub fn orphan_entries(&self) -> Vec<&Entry> {
+        self.entries.iter().filter(|x| !x.track.exists()).collect()
+    }
+
+    /// Removes all entries whose tracks do not exist on disk.
+    /// Returns the number of entries removed.
+    pub fn prune_orphans(&mut self) -> usize {
+        let indices = self.entries.iter()
+            .enumerate()
+            .filter(|(_, x)| !x.track.exists())
+            .map(|(i, _)| i)
+            .collect::<Vec<usize>>();
+        self.remove_many(&indices)
+    }
 
+    /// Removes all entries with a count of 0, which carry no information.
+    /// Returns the number of entries removed.
+    pub fn drop_zero_counts(&mut self) -> usize {
+        let indices = self.entries.iter()
+            .enumerate()
+            .filter(|(_, x)| x.count == 0)
+            .map(|(i, _)| i)
+            .collect::<Vec<usize>>();
+        self.remove_many(&indices)
+    }
+
+    /// Works like `write()`, but entries are written in descending order of their counts (ties
+    /// retain their relative order). The in-memory order and modification state are unaffected.
+    pub fn write_sorted_by_count(&self) -> Result<()> {
+        let mut entries = self.entries.iter().collect::<Vec<&Entry>>();
+        entries.sort_by_key(|x| std::cmp::Reverse(x.count));
+        self.write_entries(entries.into_iter())
+    }
+
+    /// Overwrites the playcount file with the given entries.
+    fn write_entries<'a, I: Iterator<Item = &'a Entry>>(&self, entries: I) -> Result<()> {
+        crate::write_atomic(&self.path, |file| {
+            for entry in entries {
+                match entry.timestamp_column() {
+                    Some(ts) => writeln!(file, "{}\t{}\t{}", entry.count, entry.track.path, ts)?,
+                    None => writeln!(file, "{}\t{}", entry.count, entry.track.path)?,
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Writes all entries in CSV format, with a `count,path` header row.
+    pub fn to_csv<W: Write>(&self, w: &mut W) -> Result<()> {
+        writeln!(w, "count,path")?;
+        for entry in &self.entries {
+            writeln!(w, "{},{}", entry.count, crate::csv_quote(entry.track.path.as_str()))?;
+        }
+        Ok(())
+    }
+
+    /// Writes all entries in JSON format, as an array of `{"count": ..., "path": ...}` objects.
+    pub fn to_json<W: Write>(&self, w: &mut W) -> Result<()> {
+        let objects = self.entries.iter()
+            .map(|x| format!("{{\"count\":{},\"path\":{}}}", x.count, crate::json_quote(x.track.path.as_str())))
+            .collect::<Vec<String>>();
+        writeln!(w, "[{}]", objects.join(","))?;
+        Ok(())
+    }
+
+    /// Records `count` plays of a track, by incrementing its first entry without a timestamp.
+    /// If there is no such entry, a new one is appended. Adding zero plays is a no-op.
+    pub fn add_play(&mut self, track: &Track, count: usize) {
+        self.merge_entry(&Entry::new(&track.path, count));
+    }
+
+    /// Records `count` plays of a track as a new entry, appending it directly to the file on disk
+    /// instead of rewriting it. This is cheap enough to be called on every play, at the cost of
+    /// accumulating duplicate entries (see `merge_duplicates()`). Adding zero plays is a no-op.
+    /// If `timestamp` is given, it is stored in the Unix format where possible.
+    ///
+    /// The file is created if it does not exist. If it does not end with a newline (e.g. after
+    /// editing by hand), one is added first. Unsaved modifications of other entries remain
+    /// unsaved, i.e. the modification state is unaffected.
+    pub fn append_play(&mut self, track: &Track, count: usize, timestamp: Option<DateTime<Utc>>) -> Result<()> {
+        if count == 0 {
+            return Ok(());
+        }
+        let mut file = OpenOptions::new().read(true).append(true).create(true).open(&self.path)?;
+        let len = file.metadata()?.len();
+        if len > 0 {
+            let mut last = [0u8];
+            file.seek(SeekFrom::Start(len - 1))?;
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                writeln!(file)?;
+            }
+        }
+        match timestamp {
+            Some(ts) => writeln!(file, "{}\t{}\t{}", count, track.path, Entry::format_timestamp(ts))?,
+            None => writeln!(file, "{}\t{}", count, track.path)?,
+        }
+        let mut entry = Entry::new(&track.path, count);
+        entry.timestamp = timestamp;
+        self.push_entry(entry);
+        debug_assert!(self.verify_integrity());
+        Ok(())
+    }
+
+    /// Removes `count` plays from the first entry of a track without a timestamp, i.e. the entry
+    /// `add_play()` increments, e.g. to undo an accidental call. Other entries of the same track
+    /// are never touched. If the entry's count drops to zero, the entry is removed. Subtracting
+    /// zero plays is a no-op.
+    ///
+    /// Returns an error if the track is not present, or its first entry without a timestamp has
+    /// fewer than `count` plays (or does not exist). In case of an error, the playcount is left
+    /// untouched.
+    pub fn subtract_play(&mut self, track: &Track, count: usize) -> Result<()> {
+        if count == 0 {
+            return Ok(());
+        }
+        let index = match self.tracks_map.get(track) {
+            Some(pos) => pos.iter().copied().find(|&i| self.entries[i].timestamp.is_none()),
+            None => return Err(MusicToolsError::MissingTrack { path: self.path.clone(), tracks: vec![track.clone()] }),
+        };
+        let available = index.map_or(0, |i| self.entries[i].count);
+        if available < count {
+            return Err(MusicToolsError::NotEnoughPlays { track: track.clone(), requested: count, available });
+        }
+
+        let index = index.unwrap();
+        if available == count {
+            self.remove_at(index);
+        } else {
+            self.entries[index].count -= count;
+        }
+        self.is_modified = true;
         debug_assert!(self.verify_integrity());
-        n_duplicates
+        Ok(())
+    }
+
+    /// Merges all entries from another playcount into this one. Counts are added to the first
+    /// entry of the same track and timestamp, while other entries are appended, in order of their
+    /// appearance in `other`.
+    pub fn merge_from(&mut self, other: &Playcount) {
+        for entry in &other.entries {
+            self.merge_entry(entry);
+        }
+    }
+
+    /// Returns the `n` most played tracks alongside their total counts, in descending order.
+    /// Counts of entries referring to the same track are summed up, without modifying the
+    /// playcount (see `merge_duplicates()`). Ties are broken by the order of first appearance.
+    /// If `n` exceeds the number of unique tracks, all of them are returned.
+    pub fn top_n(&self, n: usize) -> Vec<(&Track, usize)> {
+        let mut totals = self.totals()
+            .map(|(track, count)| (track, count, self.tracks_map[track][0]))
+            .collect::<Vec<(&Track, usize, usize)>>();
+        totals.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.2.cmp(&b.2)));
+        totals.into_iter()
+            .take(n)
+            .map(|(track, count, _)| (track, count))
+            .collect()
+    }
+
+    /// Computes summary statistics of the playcount. Entries referring to the same track are
+    /// treated as one, with their counts summed up.
+    pub fn stats(&self) -> PlaycountStats {
+        let mut totals = self.totals()
+            .map(|(_, count)| count)
+            .collect::<Vec<usize>>();
+        totals.sort_unstable();
+
+        let total_plays = self.total_plays();
+        let unique_tracks = totals.len();
+        let (mean_plays, median_plays) = match unique_tracks {
+            0 => (0.0, 0.0),
+            n if n % 2 == 0 => (
+                total_plays as f64 / n as f64,
+                (totals[n / 2 - 1] + totals[n / 2]) as f64 / 2.0,
+            ),
+            n => (total_plays as f64 / n as f64, totals[n / 2] as f64),
+        };
+        let most_played = self.top_n(1)
+            .into_iter()
+            .next()
+            .map(|(track, count)| (track.clone(), count));
+
+        PlaycountStats {
+            total_plays,
+            unique_tracks,
+            mean_plays,
+            median_plays,
+            most_played,
+        }
+    }
+
+    /// Creates a new playlist tied to `fpath`, containing the `n` most played tracks in order of
+    /// their ranking (see `top_n()`). Each track appears in the playlist only once, regardless of
+    /// how many entries refer to it. The playlist is not written to disk.
+    pub fn to_playlist<T: AsRef<Utf8Path>>(&self, fpath: T, n: usize) -> Result<Playlist> {
+        let mut playlist = Playlist::new(fpath)?;
+        for (track, _) in self.top_n(n) {
+            playlist.add_track(track.clone());
+        }
+        Ok(playlist)
     }
 }
 
 impl TracksFile for Playcount {
     fn open<T: AsRef<Utf8Path>>(fpath: T) -> Result<Self> {
-        let mut pc = Self::new(fpath)?;
-
-        let file = BufReader::new(File::open(&pc.path)?);
-        for (i, line) in file.lines().enumerate() {
-            let line = match line {
-                Ok(str) => str,
-                Err(e) => return Err(anyhow!("Failed to read line {} in '{}': {}", i, pc.path, e)),
-            };
-            let entry = match line.parse::<Entry>() {
-                Ok(entry) => entry,
-                Err(e) => {
-                    warn!("Failed to parse line {} in '{}': {}, skipping", i, pc.path, e);
-                    continue;
-                },
-            };
-            if pc.tracks_map.contains_key(&entry.track) {
-                pc.tracks_map.get_mut(&entry.track)
-                    .unwrap()
-                    .push(pc.entries.len());
-                pc.entries.push(entry);
-            } else {
-                let list = vec![pc.entries.len()];
-                pc.tracks_map.insert(entry.track.clone(), list);
-                pc.entries.push(entry);
-            }
-        }
-        debug_assert!(pc.verify_integrity());
-        Ok(pc)
+        Self::open_with(fpath, |path, n, _, e| {
+            warn!("Failed to parse line {} in '{}': {}, skipping", n, path, e);
+        })
     }
 
     fn new<T: AsRef<Utf8Path>>(fpath: T) -> Result<Self> {
@@ -213,13 +604,7 @@ impl TracksFile for Playcount {
     }
 
     fn write(&mut self) -> Result<()> {
-        let mut file = File::create(&self.path)?;
-        writeln!(file, "{}",
-            self.entries.iter()
-                .map(|x| format!("{}\t{}", x.count, x.track.path))
-                .collect::<Vec<String>>()
-                .join("\n")
-        )?;
+        self.write_entries(self.entries.iter())?;
         self.is_modified = false;
         Ok(())
     }
@@ -242,12 +627,9 @@ impl TracksFile for Playcount {
         self.entries.remove(index);
 
         // Shift all higher indices down by one
-        for entry in &self.entries[index..] {
-            for i in self.tracks_map.get_mut(&entry.track).unwrap() {
-                assert!(*i != index);
-                if *i > index {
-                    *i -= 1;
-                }
+        for indices in self.tracks_map.values_mut() {
+            for i in indices.iter_mut().filter(|i| **i > index) {
+                *i -= 1;
             }
         }
         self.is_modified = true;
@@ -258,13 +640,74 @@ impl TracksFile for Playcount {
         if !self.tracks_map.contains_key(track) {
             return 0;
         }
-        let mut indices = self.tracks_map[track].clone();
-        indices.sort_unstable();
-        for index in indices.iter().rev() {
-            self.remove_at(*index);
+        let indices = self.tracks_map[track].clone();
+        self.remove_many(&indices)
+    }
+
+    fn swap_remove_at(&mut self, index: usize) {
+        if index >= self.entries.len() {
+            warn!("Out-of-bounds swap_remove_at requested (index: {}, len: {})", index, self.entries.len());
+            return;
         }
+
+        // Remove index pointing at the given track from `tracks_map`
+        let track = &self.entries[index].track;
+        // If either unwrap here fails, it means `tracks_map` got corrupt somehow
+        let map_index = self.tracks_map[track].iter().position(|&x| x == index).unwrap();
+        self.tracks_map.get_mut(track).unwrap().remove(map_index);
+        if self.tracks_map[track].is_empty() {
+            self.tracks_map.remove(track);
+        }
+
+        // Repoint the last track's index to the vacated slot
+        let last = self.entries.len() - 1;
+        if index != last {
+            let indices = self.tracks_map.get_mut(&self.entries[last].track).unwrap();
+            let map_index = indices.iter().position(|&x| x == last).unwrap();
+            indices.remove(map_index);
+            let pos = indices.partition_point(|&i| i < index);
+            indices.insert(pos, index);
+        }
+
+        self.entries.swap_remove(index);
         self.is_modified = true;
-        indices.len()
+        debug_assert!(self.verify_integrity());
+    }
+
+    fn remove_many(&mut self, indices: &[usize]) -> usize {
+        let mut is_removed = vec![false; self.entries.len()];
+        let mut n_removed = 0usize;
+        for &index in indices {
+            if index >= self.entries.len() {
+                warn!("Out-of-bounds remove_many requested (index: {}, len: {})", index, self.entries.len());
+                continue;
+            }
+            if !is_removed[index] {
+                is_removed[index] = true;
+                n_removed += 1;
+            }
+        }
+        if n_removed == 0 {
+            return 0;
+        }
+
+        let entries = std::mem::take(&mut self.entries);
+        self.entries = entries.into_iter()
+            .zip(is_removed)
+            .filter_map(|(entry, is_removed)| (!is_removed).then_some(entry))
+            .collect();
+        self.rebuild_tracks_map();
+        self.is_modified = true;
+        n_removed
+    }
+
+    /// Unlike the default implementation, duplicate entries have their counts added to the first
+    /// entry of the track. Timestamps of the removed entries are lost, see `merge_duplicates()`
+    /// for a variant which keeps entries with different timestamps apart.
+    fn dedup(&mut self) -> usize {
+        self.dedup_with(|pc, first, dupes| {
+            pc.entries[first].count += dupes.iter().map(|&x| pc.entries[x].count).sum::<usize>();
+        })
     }
 
     fn bulk_rename(&mut self, edits: &HashMap<Track, Utf8PathBuf>) -> usize {
@@ -0,0 +1,21 @@
+use crate::track::Track;
+
+/// Summary statistics of a playcount, computed from per-track total counts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaycountStats {
+    /// The total number of plays across all entries.
+    pub total_plays: usize,
+
+    /// The number of unique tracks.
+    pub unique_tracks: usize,
+
+    /// The mean number of plays per unique track (0 if there are no tracks).
+    pub mean_plays: f64,
+
+    /// The median number of plays per unique track (0 if there are no tracks).
+    pub median_plays: f64,
+
+    /// The most played track alongside its total count, if any. Ties are broken by the order of
+    /// first appearance in the playcount.
+    pub most_played: Option<(Track, usize)>,
+}
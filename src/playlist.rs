@@ -1,14 +1,143 @@
-pub use crate::tracksfile::TracksFile;
+pub use crate::tracksfile::{PathMapping, TracksFile, TracksFileSummary};
 
+use crate::config::Config;
 use crate::music_dir;
 use crate::track::Track;
-use anyhow::{anyhow, Result};
+use crate::error::{MusicToolsError, Result};
 use camino::{Utf8Path, Utf8PathBuf};
 use log::{error, warn};
+use rand::SeedableRng;
+use rand::seq::SliceRandom;
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::{Write, BufRead, BufReader};
+use std::fs::{self, File};
+use std::io::{self, Write, BufRead, BufReader};
 use std::sync::OnceLock;
+use std::time::SystemTime;
+
+/// Extended M3U metadata of a track, i.e. the contents of an `#EXTINF:duration,title` line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtInf {
+    /// Track length in seconds. By convention, -1 means unknown.
+    pub duration: f64,
+
+    /// Display title, usually in the form of "Artist - Title".
+    pub title: String,
+}
+
+impl std::str::FromStr for ExtInf {
+    type Err = MusicToolsError;
+
+    /// Parses the part of an `#EXTINF:` line after the colon.
+    fn from_str(str: &str) -> Result<Self> {
+        let (duration_str, title) = match str.split_once(',') {
+            Some(split) => split,
+            None => return Err(MusicToolsError::parse(format!("Missing comma in EXTINF '{}'", str))),
+        };
+        let duration = match duration_str.trim().parse::<f64>() {
+            Ok(num) => num,
+            Err(e) => return Err(MusicToolsError::parse(format!("Failed to convert EXTINF duration '{}' to number: {}", duration_str, e))),
+        };
+        Ok(ExtInf { duration, title: title.to_string() })
+    }
+}
+
+/// The outcome of `Playlist::refresh_extinf()`. Duplicate tracks are counted.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct ExtInfRefresh {
+    /// The number of tracks whose metadata changed.
+    pub n_updated: usize,
+
+    /// The number of tracks whose files could not be read.
+    pub n_skipped: usize,
+}
+
+impl std::fmt::Display for ExtInf {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "#EXTINF:{},{}", self.duration, self.title)
+    }
+}
+
+/// Criteria by which playlist tracks can be sorted.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SortKey {
+    /// Lexical order of the whole track path.
+    Path,
+
+    /// Lexical order of the file name (the last path component).
+    FileName,
+}
+
+/// Line terminators used when writing a playlist file.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum LineEnding {
+    /// Unix-style `\n`.
+    #[default]
+    Lf,
+
+    /// Windows-style `\r\n`, for players which do not handle bare `\n`.
+    Crlf,
+}
+
+impl LineEnding {
+    fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+        }
+    }
+}
+
+/// An advisory diagnostic about a likely mistake in a playlist (see `Playlist::lint()`).
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum PlaylistLint {
+    /// The same track appears twice in a row, at `index` and `index + 1`.
+    ConsecutiveDuplicate { index: usize, track: Track },
+
+    /// The playlist mixes absolute and relative paths.
+    MixedPaths { n_absolute: usize, n_relative: usize },
+
+    /// The track at `index` does not exist on disk.
+    MissingFile { index: usize, track: Track },
+}
+
+impl std::fmt::Display for PlaylistLint {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PlaylistLint::ConsecutiveDuplicate { index, track } =>
+                write!(f, "'{}' repeats consecutively at indices {} and {}", track, index, index + 1),
+            PlaylistLint::MixedPaths { n_absolute, n_relative } =>
+                write!(f, "mixed absolute ({}) and relative ({}) paths", n_absolute, n_relative),
+            PlaylistLint::MissingFile { index, track } =>
+                write!(f, "'{}' at index {} does not exist", track, index),
+        }
+    }
+}
+
+/// Non-track lines preceding a track in the file. These are kept per line position, rather than
+/// per track, so that a file is written back exactly as it was read, even if a track repeats.
+#[derive(Debug, Clone, Default)]
+struct Preamble {
+    /// Comments and blank lines preceding the track, verbatim.
+    comments: Vec<String>,
+
+    /// Extended M3U metadata of the track.
+    extinf: Option<ExtInf>,
+
+    /// The number of trailing `comments` which come between the EXTINF line and the track.
+    n_after_extinf: usize,
+}
+
+impl Preamble {
+    /// Splits `comments` into those preceding and following the EXTINF line.
+    fn split_comments(&self) -> (&[String], &[String]) {
+        self.comments.split_at(self.comments.len() - self.n_after_extinf)
+    }
+}
+
+/// File extensions recognized as audio files by default, e.g. in `Playlist::from_dir()`.
+pub const DEFAULT_AUDIO_EXTENSIONS: &[&str] = &["mp3", "flac", "ogg"];
 
 #[derive(Debug)]
 pub struct Playlist {
@@ -19,6 +148,21 @@ pub struct Playlist {
     /// Cached index for `tracks`, to avoid linear search.
     tracks_map: HashMap<Track, Vec<usize>>,
 
+    /// Non-track lines preceding each track, parallel to `tracks`.
+    preambles: Vec<Preamble>,
+
+    /// Comments and blank lines following the last track, verbatim.
+    trailer: Vec<String>,
+
+    /// Whether the file is in the extended M3U format, i.e. starts with an `#EXTM3U` header.
+    is_extended: bool,
+
+    /// Line terminators used by `write()`.
+    line_ending: LineEnding,
+
+    /// Modification time of the file at the last `open()` or `write()`, if either took place.
+    mtime: Option<SystemTime>,
+
     /// Whether the playlist was modified since the last `write`.
     is_modified: bool,
 }
@@ -27,7 +171,7 @@ impl Playlist {
     /// Returns the path to the playlists directory.
     fn playlist_dir() -> &'static Utf8Path {
         static PLAYLISTS_DIR: OnceLock<Utf8PathBuf> = OnceLock::new();
-        PLAYLISTS_DIR.get_or_init(|| music_dir().join("Playlists"))
+        PLAYLISTS_DIR.get_or_init(Config::playlist_dir)
     }
 
     /// Returns the path to the ignore playlist file. This is a meta-playlist that stores invalid
@@ -37,47 +181,346 @@ impl Playlist {
         IGNORE_FILE.get_or_init(|| music_dir().join(".ignore.m3u"))
     }
 
-    // Pushes a new track to the end of the playlist.
-    pub fn push(&mut self, track: Track) {
-        if self.tracks_map.contains_key(&track) {
-            self.tracks_map.get_mut(&track).unwrap().push(self.tracks.len());
-        } else {
-            self.tracks_map.insert(track.clone(), vec![self.tracks.len()]);
+    /// Returns the line terminators used when writing the playlist.
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    /// Sets the line terminators used when writing the playlist. The default is `LineEnding::Lf`,
+    /// regardless of the line terminators used in the file the playlist was read from.
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        if self.line_ending != line_ending {
+            self.line_ending = line_ending;
+            self.is_modified = true;
         }
-        self.tracks.push(track);
+    }
+
+    /// Returns whether the file has been modified on disk since the playlist was last read from
+    /// or written to it, e.g. by another program. Playlists which were never read nor written
+    /// (see `new()`) are never stale.
+    ///
+    /// Returns an error if the file's modification time cannot be read, e.g. because it was
+    /// removed.
+    pub fn stale(&self) -> Result<bool> {
+        match self.mtime {
+            Some(mtime) => Ok(fs::metadata(&self.path)?.modified()? != mtime),
+            None => Ok(false),
+        }
+    }
+
+    /// Appends a new track to the end of the playlist.
+    pub fn add_track(&mut self, track: Track) {
+        self.push_track(track, Preamble::default());
         self.is_modified = true;
         debug_assert!(self.verify_integrity());
     }
 
-    /// Removes all duplicate tracks from the playlist, leaving only the first occurrence of each.
-    /// Returns the number of tracks removed.
-    pub fn remove_duplicates(&mut self) -> usize {
-        // Build a list of all indices to remove
-        let mut indices = Vec::new();
-        for pos in self.tracks_map.values() {
-            if pos.len() > 1 {
-                indices.extend_from_slice(&pos[1..]);
+    /// Appends a track to `tracks`, keeping `tracks_map` in sync. Unlike `add_track()`, this does
+    /// not mark the playlist as modified.
+    fn push_track(&mut self, track: Track, preamble: Preamble) {
+        crate::tracksfile::index_track(&mut self.tracks_map, &track, self.tracks.len());
+        self.tracks.push(track);
+        self.preambles.push(preamble);
+    }
+
+    /// Prepends comments of a removed track to the preamble of the track now at `index`, or to the
+    /// trailer if there is none, so that they stay in place.
+    fn keep_comments(&mut self, mut comments: Vec<String>, index: usize) {
+        if comments.is_empty() {
+            return;
+        }
+        let target = match self.preambles.get_mut(index) {
+            Some(preamble) => &mut preamble.comments,
+            None => &mut self.trailer,
+        };
+        comments.append(target);
+        *target = comments;
+    }
+
+    /// Inserts a new track at the given index, shifting all tracks after it to the right.
+    pub fn insert_track_at(&mut self, index: usize, track: Track) {
+        if index > self.tracks.len() {
+            warn!("Out-of-bounds insert_track_at requested (index: {}, len: {})", index, self.tracks.len());
+            return;
+        }
+
+        // Shift all indices at or above `index` up by one
+        for indices in self.tracks_map.values_mut() {
+            for i in indices.iter_mut().filter(|i| **i >= index) {
+                *i += 1;
             }
         }
 
-        let n_duplicates = indices.len();
+        match self.tracks_map.get_mut(&track) {
+            Some(indices) => {
+                let pos = indices.partition_point(|&i| i < index);
+                indices.insert(pos, index);
+            },
+            None => { self.tracks_map.insert(track.clone(), vec![index]); },
+        }
+        self.tracks.insert(index, track);
+        self.preambles.insert(index, Preamble::default());
+        self.is_modified = true;
+        debug_assert!(self.verify_integrity());
+    }
+
+    /// Appends all tracks from another playlist, in order, including duplicates.
+    /// Extended M3U metadata is carried over, but comments are not.
+    pub fn extend_from(&mut self, other: &Playlist) {
+        for (track, preamble) in other.tracks.iter().zip(&other.preambles) {
+            self.push_track(track.clone(), Preamble { extinf: preamble.extinf.clone(), ..Default::default() });
+        }
+        self.is_modified |= !other.tracks.is_empty();
+        debug_assert!(self.verify_integrity());
+    }
 
-        // Remove the indices
-        if !indices.is_empty() {
-            indices.sort_unstable();
-            indices.into_iter().rev().for_each(|x| self.remove_at(x));
+    /// Appends tracks from another playlist which are not yet present in this one, in order of
+    /// their first appearance in `other`. Extended M3U metadata is carried over, but comments are
+    /// not.
+    /// Returns the number of tracks added.
+    pub fn union(&mut self, other: &Playlist) -> usize {
+        let mut n_added = 0usize;
+        for (track, preamble) in other.tracks.iter().zip(&other.preambles) {
+            if !self.contains(track) {
+                self.push_track(track.clone(), Preamble { extinf: preamble.extinf.clone(), ..Default::default() });
+                n_added += 1;
+            }
+        }
+        if n_added > 0 {
             self.is_modified = true;
         }
         debug_assert!(self.verify_integrity());
+        n_added
+    }
 
-        n_duplicates
+    /// Removes all tracks which are not present in another playlist.
+    /// Returns the number of tracks removed.
+    pub fn intersection(&mut self, other: &Playlist) -> usize {
+        let indices = self.tracks.iter()
+            .enumerate()
+            .filter(|(_, x)| !other.contains(x))
+            .map(|(i, _)| i)
+            .collect::<Vec<usize>>();
+        self.remove_many(&indices)
+    }
+
+    /// Removes all tracks which are present in another playlist.
+    /// Returns the number of tracks removed.
+    pub fn difference(&mut self, other: &Playlist) -> usize {
+        let indices = self.tracks.iter()
+            .enumerate()
+            .filter(|(_, x)| other.contains(x))
+            .map(|(i, _)| i)
+            .collect::<Vec<usize>>();
+        self.remove_many(&indices)
+    }
+
+    /// Partitions the tracks into new playlists of at most `chunk` tracks each, in order.
+    /// The playlists are placed next to this one and named after it, e.g. `name.1.m3u`,
+    /// `name.2.m3u`, etc. None of them are written to disk.
+    ///
+    /// Extended M3U metadata, comments and line endings are carried over. A `chunk` of 0 yields
+    /// no playlists.
+    pub fn split(&self, chunk: usize) -> Vec<Playlist> {
+        if chunk == 0 {
+            warn!("Requested split of '{}' into chunks of 0 tracks", self.path);
+            return Vec::new();
+        }
+        self.tracks.chunks(chunk)
+            .zip(self.preambles.chunks(chunk))
+            .enumerate()
+            .map(|(i, (tracks, preambles))| {
+                let name = format!("{}.{}", self.name, i + 1);
+                let fname = match self.path.extension() {
+                    Some(ext) => format!("{}.{}", name, ext),
+                    None => name.clone(),
+                };
+                // Cannot fail, as the path always has a filename
+                let mut pl = Self::new(self.path.with_file_name(fname)).unwrap();
+                pl.name = name;
+                pl.is_extended = self.is_extended;
+                pl.line_ending = self.line_ending;
+                for (track, preamble) in tracks.iter().zip(preambles) {
+                    pl.push_track(track.clone(), preamble.clone());
+                }
+                pl.is_modified = true;
+                pl
+            })
+            .collect()
+    }
+
+    /// Reverses the order of tracks.
+    pub fn reverse(&mut self) {
+        self.tracks.reverse();
+        self.preambles.reverse();
+        self.rebuild_tracks_map();
+        self.is_modified = true;
+    }
+
+    /// Moves a track from one index to another, shifting the tracks in between.
+    pub fn move_track(&mut self, from: usize, to: usize) {
+        if from >= self.tracks.len() || to >= self.tracks.len() {
+            warn!("Out-of-bounds move_track requested (from: {}, to: {}, len: {})", from, to, self.tracks.len());
+            return;
+        }
+        if from == to {
+            return;
+        }
+        let track = self.tracks.remove(from);
+        self.tracks.insert(to, track);
+        let preamble = self.preambles.remove(from);
+        self.preambles.insert(to, preamble);
+        self.rebuild_tracks_map();
+        self.is_modified = true;
+    }
+
+    /// Randomly reorders the tracks. If `seed` is given, the resulting order is reproducible,
+    /// otherwise the random generator is seeded from system entropy.
+    /// Comments and extended M3U metadata move along with the tracks they precede.
+    pub fn shuffle(&mut self, seed: Option<u64>) {
+        let mut rng = match seed {
+            Some(seed) => ChaCha8Rng::seed_from_u64(seed),
+            None => ChaCha8Rng::from_entropy(),
+        };
+        let mut order = (0..self.tracks.len()).collect::<Vec<usize>>();
+        order.shuffle(&mut rng);
+        self.reorder(&order);
+    }
+
+    /// Sorts the tracks by a given key. The sort is stable, i.e. tracks with equal keys retain
+    /// their relative order. Comments and extended M3U metadata move along with the tracks they
+    /// precede.
+    pub fn sort(&mut self, key: SortKey) {
+        let mut order = (0..self.tracks.len()).collect::<Vec<usize>>();
+        match key {
+            SortKey::Path => order.sort_by(|&a, &b| self.tracks[a].path.cmp(&self.tracks[b].path)),
+            SortKey::FileName => order.sort_by(|&a, &b| self.tracks[a].path.file_name().cmp(&self.tracks[b].path.file_name())),
+        }
+        self.reorder(&order);
+    }
+
+    /// Rearranges the tracks and their preambles, so that the i-th track becomes the one at index
+    /// `order[i]`. `order` must be a permutation of all indices.
+    fn reorder(&mut self, order: &[usize]) {
+        let mut tracks = std::mem::take(&mut self.tracks).into_iter().map(Some).collect::<Vec<_>>();
+        let mut preambles = std::mem::take(&mut self.preambles).into_iter().map(Some).collect::<Vec<_>>();
+        for &i in order {
+            // If either unwrap here fails, `order` is not a permutation
+            self.tracks.push(tracks[i].take().unwrap());
+            self.preambles.push(preambles[i].take().unwrap());
+        }
+        self.rebuild_tracks_map();
+        self.is_modified = true;
+    }
+
+    /// Rewrites absolute track paths under `base` to be relative to it. Tracks which are already
+    /// relative, or lie outside of `base`, are left unchanged.
+    /// See `map_paths()` for the return value.
+    pub fn to_relative(&mut self, base: &Utf8Path) -> PathMapping {
+        self.map_paths(|path| Track::new(path).to_relative(base).path)
+    }
+
+    /// Rewrites relative track paths to be absolute, by prefixing them with `base`. Tracks which
+    /// are already absolute are left unchanged.
+    /// See `map_paths()` for the return value.
+    pub fn to_absolute(&mut self, base: &Utf8Path) -> PathMapping {
+        self.map_paths(|path| Track::new(path).to_absolute(base).path)
+    }
+
+    /// Writes the playlist in the PLS format. Titles and lengths are filled in from extended M3U
+    /// metadata, where available.
+    pub fn to_pls<W: Write>(&self, w: &mut W) -> Result<()> {
+        writeln!(w, "[playlist]")?;
+        for (i, (track, preamble)) in self.tracks.iter().zip(&self.preambles).enumerate() {
+            writeln!(w, "File{}={}", i + 1, track.path)?;
+            if let Some(extinf) = &preamble.extinf {
+                writeln!(w, "Title{}={}", i + 1, extinf.title)?;
+                writeln!(w, "Length{}={}", i + 1, extinf.duration)?;
+            }
+        }
+        writeln!(w, "NumberOfEntries={}", self.tracks.len())?;
+        writeln!(w, "Version=2")?;
+        Ok(())
+    }
+
+    /// Writes the playlist in JSON format, as an array of track paths.
+    pub fn to_json<W: Write>(&self, w: &mut W) -> Result<()> {
+        let paths = self.tracks.iter()
+            .map(|x| crate::json_quote(x.path.as_str()))
+            .collect::<Vec<String>>();
+        writeln!(w, "[{}]", paths.join(","))?;
+        Ok(())
+    }
+
+    /// Returns all tracks which do not exist on disk, in order of appearance.
+    pub fn missing_tracks(&self) -> Vec<&Track> {
+        self.tracks.iter().filter(|x| !x.exists()).collect()
+    }
+
+    /// Removes all tracks which do not exist on disk.
+    /// Returns the number of tracks removed.
+    pub fn prune_missing(&mut self) -> usize {
+        let indices = self.tracks.iter()
+            .enumerate()
+            .filter(|(_, x)| !x.exists())
+            .map(|(i, _)| i)
+            .collect::<Vec<usize>>();
+        self.remove_many(&indices)
+    }
+
+    /// Works like `iter()`, but opens all files in parallel and returns them at once, in the same
+    /// order as `iter()` would.
+    pub fn par_iter() -> Option<Vec<Self>> {
+        let paths = match Self::iter_paths() {
+            Ok(it) => it.collect::<Vec<Utf8PathBuf>>(),
+            Err(e) => {
+                error!("Failed to list the playlists directory '{:?}': {}", Self::playlist_dir(), e);
+                return None;
+            },
+        };
+        let playlists = paths.into_par_iter()
+            .filter_map(|path|
+                match Self::open(&path) {
+                    Ok(playlist) => Some(playlist),
+                    Err(e) => {
+                        warn!("Failed to read playlist '{:?}': {}, skipping", path, e);
+                        None
+                    },
+                }
+            )
+            .collect();
+        Some(playlists)
+    }
+
+    /// Checks the playlist for likely mistakes, such as consecutive duplicate tracks, mixing of
+    /// absolute and relative paths, and missing files. Nothing is modified.
+    ///
+    /// This checks every track's existence on disk, so it is comparatively slow.
+    pub fn lint(&self) -> Vec<PlaylistLint> {
+        let mut lints = Vec::new();
+        for (index, pair) in self.tracks.windows(2).enumerate() {
+            if pair[0] == pair[1] {
+                lints.push(PlaylistLint::ConsecutiveDuplicate { index, track: pair[0].clone() });
+            }
+        }
+        let n_absolute = self.tracks.iter().filter(|x| x.path.is_absolute()).count();
+        let n_relative = self.tracks.len() - n_absolute;
+        if n_absolute > 0 && n_relative > 0 {
+            lints.push(PlaylistLint::MixedPaths { n_absolute, n_relative });
+        }
+        for (index, track) in self.tracks.iter().enumerate() {
+            if !track.exists() {
+                lints.push(PlaylistLint::MissingFile { index, track: track.clone() });
+            }
+        }
+        lints
     }
 
     /// Returns an iterator over all playlist file paths.
     fn iter_paths() -> Result<impl Iterator<Item = Utf8PathBuf>> {
         crate::iter_paths(
             Self::playlist_dir(),
-            |x| x.is_file() && x.extension().is_some_and(|y| y == "m3u")
+            |x| x.is_file() && x.extension().is_some_and(|y| y == "m3u" || y == "m3u8")
         )
     }
 
@@ -85,11 +528,7 @@ impl Playlist {
     fn rebuild_tracks_map(&mut self) {
         self.tracks_map.clear();
         for (i, track) in self.tracks.iter().enumerate() {
-            if self.tracks_map.contains_key(track) {
-                self.tracks_map.get_mut(track).unwrap().push(i);
-            } else {
-                self.tracks_map.insert(track.clone(), vec![i]);
-            }
+            crate::tracksfile::index_track(&mut self.tracks_map, track, i);
         }
         debug_assert!(self.verify_integrity());
     }
@@ -97,6 +536,9 @@ impl Playlist {
     /// Verifies the integrity of the struct. This is quite slow and intended for use with
     /// `debug_assert`.
     fn verify_integrity(&self) -> bool {
+        if self.preambles.len() != self.tracks.len() {
+            return false;
+        }
         for (i, track) in self.tracks.iter().enumerate() {
             if !self.tracks_map.contains_key(track) {
                 return false;
@@ -120,36 +562,149 @@ impl Playlist {
     pub fn name(&self) -> &String {
         &self.name
     }
+
+    /// Creates a new playlist tied to `fpath`, containing all audio files found in `dir` (and its
+    /// subdirectories, if `recursive`), sorted by filename. Audio files are recognized by their
+    /// extension, regardless of case (see `DEFAULT_AUDIO_EXTENSIONS`). Symlinked directories are
+    /// not descended into, to avoid cycles. Paths inside `MUSIC_DIR` are stored relative to it.
+    /// The playlist is not written to disk.
+    pub fn from_dir<T, U>(fpath: T, dir: U, recursive: bool, extensions: &[&str]) -> Result<Self>
+    where
+        T: AsRef<Utf8Path>,
+        U: AsRef<Utf8Path>,
+    {
+        let mut pl = Self::new(fpath)?;
+        let mut dirs = vec![dir.as_ref().to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            let paths = crate::iter_paths(&dir, |x| {
+                (recursive && x.is_dir() && !x.is_symlink())
+                    || (x.is_file() && x.extension().is_some_and(|y| extensions.iter().any(|z| z.eq_ignore_ascii_case(y))))
+            })?;
+            for path in paths {
+                if path.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+                let path = path.strip_prefix(music_dir()).unwrap_or(&path);
+                pl.add_track(Track::new(path));
+            }
+        }
+        pl.sort(SortKey::FileName);
+        Ok(pl)
+    }
+
+    /// Returns the extended M3U metadata of a track, if any. If the track occurs more than once,
+    /// the metadata of the first occurrence which has any is returned (see `extinf_at()`).
+    pub fn extinf(&self, track: &Track) -> Option<&ExtInf> {
+        self.tracks_map.get(track)?
+            .iter()
+            .find_map(|&i| self.preambles[i].extinf.as_ref())
+    }
+
+    /// Returns the extended M3U metadata of the track at a given index, if any.
+    pub fn extinf_at(&self, index: usize) -> Option<&ExtInf> {
+        self.preambles.get(index)?.extinf.as_ref()
+    }
+
+    /// Regenerates extended M3U metadata of all tracks from their ID3v2 tags (see
+    /// `Track::metadata()`). Titles take the form "Artist - Title". Fields missing from a tag
+    /// keep their existing values, or fall back to the file name and an unknown (-1) duration.
+    /// Tracks whose files cannot be read are skipped, keeping their existing metadata.
+    ///
+    /// Returns the number of updated and skipped tracks.
+    pub fn refresh_extinf(&mut self) -> Result<ExtInfRefresh> {
+        let mut n_updated = 0usize;
+        let mut n_skipped = 0usize;
+        for (track, positions) in &self.tracks_map {
+            let metadata = match track.metadata() {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    warn!("Failed to read metadata of '{}': {}, skipping", track, e);
+                    n_skipped += positions.len();
+                    continue;
+                },
+            };
+            for &i in positions {
+                let old = self.preambles[i].extinf.as_ref();
+                let title = match (&metadata.artist, &metadata.title) {
+                    (Some(artist), Some(title)) => format!("{} - {}", artist, title),
+                    (None, Some(title)) => title.clone(),
+                    _ => match old {
+                        Some(extinf) => extinf.title.clone(),
+                        None => track.path.file_stem().unwrap_or(track.path.as_str()).to_string(),
+                    },
+                };
+                let duration = match (metadata.duration, old) {
+                    (Some(duration), _) => duration.as_secs_f64().round(),
+                    (None, Some(extinf)) => extinf.duration,
+                    (None, None) => -1.0,
+                };
+                let extinf = ExtInf { duration, title };
+                if old != Some(&extinf) {
+                    self.preambles[i].extinf = Some(extinf);
+                    n_updated += 1;
+                }
+            }
+        }
+        if n_updated > 0 {
+            self.is_modified = true;
+        }
+        Ok(ExtInfRefresh { n_updated, n_skipped })
+    }
 }
 
 impl TracksFile for Playlist {
     fn open<T: AsRef<Utf8Path>>(fpath: T) -> Result<Self> {
         let mut pl = Self::new(fpath)?;
 
-        let file = BufReader::new(File::open(&pl.path)?);
-        for line in file.lines() {
-            let line = match line {
-                Ok(str) => str,
-                Err(e) => return Err(anyhow!("Failed to read line from '{}': {}", pl.path, e)),
-            };
-            let track = Track::new(&line);
-            if pl.tracks_map.contains_key(&track) {
-                pl.tracks_map.get_mut(&track)
-                    .unwrap()
-                    .push(pl.tracks.len());
-                pl.tracks.push(track);
-            } else {
-                let list = vec![pl.tracks.len()];
-                pl.tracks_map.insert(track.clone(), list);
-                pl.tracks.push(track);
+        // Non-track lines collected for the next track line, and the verbatim EXTINF line among them
+        let mut preamble = Preamble::default();
+        let mut extinf_line = String::new();
+
+        let file = File::open(&pl.path)?;
+        pl.mtime = Some(file.metadata()?.modified()?);
+        let file = BufReader::new(file);
+        for (i, line) in file.lines().enumerate() {
+            let line = line?;
+            // `lines()` strips "\r\n", but a stray carriage return must not end up in a path
+            let line = line.trim_end_matches('\r');
+            // Files from other players, `.m3u8` ones especially, often start with a byte order mark
+            let line = if i == 0 { line.strip_prefix('\u{feff}').unwrap_or(line) } else { line };
+            if line == "#EXTM3U" {
+                pl.is_extended = true;
+                continue;
+            }
+            if let Some(str) = line.strip_prefix("#EXTINF:") {
+                match str.parse::<ExtInf>() {
+                    Ok(extinf) => {
+                        // A dangling EXTINF line is kept verbatim where it was, rather than lost
+                        if preamble.extinf.replace(extinf).is_some() {
+                            let pos = preamble.comments.len() - preamble.n_after_extinf;
+                            preamble.comments.insert(pos, std::mem::replace(&mut extinf_line, line.to_string()));
+                        } else {
+                            extinf_line = line.to_string();
+                        }
+                        preamble.n_after_extinf = 0;
+                        continue;
+                    },
+                    Err(e) => warn!("Failed to parse line {} in '{}': {}, keeping it as a comment", i + 1, pl.path, e),
+                }
+            }
+            // Keep comments and blank lines, to write them back later
+            if line.starts_with('#') || line.trim().is_empty() {
+                preamble.comments.push(line.to_string());
+                if preamble.extinf.is_some() {
+                    preamble.n_after_extinf += 1;
+                }
+                continue;
             }
+            pl.push_track(Track::new(line), std::mem::take(&mut preamble));
         }
-
-        // Don't represent empty files with a single empty track
-        if pl.tracks.len() == 1 && pl.tracks[0].path.as_str().is_empty() {
-            pl.tracks.clear();
-            pl.tracks_map.clear();
+        if preamble.extinf.is_some() {
+            let pos = preamble.comments.len() - preamble.n_after_extinf;
+            preamble.comments.insert(pos, extinf_line);
         }
+        pl.trailer = preamble.comments;
 
         debug_assert!(pl.verify_integrity());
         Ok(pl)
@@ -161,11 +716,16 @@ impl TracksFile for Playlist {
             name: String::with_capacity(64),
             tracks: Vec::new(),
             tracks_map: HashMap::new(),
+            preambles: Vec::new(),
+            trailer: Vec::new(),
+            is_extended: false,
+            line_ending: LineEnding::default(),
+            mtime: None,
             is_modified: false,
         };
         match pl.path.file_stem() {
             Some(name) => pl.name.push_str(name),
-            None => return Err(anyhow!("Failed to extract filename from '{:?}'", pl.path)),
+            None => return Err(MusicToolsError::InvalidPath(format!("Failed to extract filename from '{:?}'", pl.path))),
         }
         Ok(pl)
     }
@@ -177,6 +737,15 @@ impl TracksFile for Playlist {
         }
     }
 
+    /// Unlike the default implementation, the line ending is kept, since it is not read from the
+    /// file (see `set_line_ending()`).
+    fn reload(&mut self) -> Result<()> {
+        let line_ending = self.line_ending;
+        *self = Self::open(&self.path)?;
+        self.line_ending = line_ending;
+        Ok(())
+    }
+
     fn iter() -> Option<impl Iterator<Item = Self>> {
         let it = match Self::iter_paths() {
             Ok(it) => it,
@@ -222,13 +791,33 @@ impl TracksFile for Playlist {
     }
 
     fn write(&mut self) -> Result<()> {
-        let mut file = File::create(&self.path)?;
-        writeln!(file, "{}",
-            self.tracks.iter()
-                .map(|x| x.path.clone().into_string())
-                .collect::<Vec<String>>()
-                .join("\n")
-        )?;
+        let eol = self.line_ending.as_str();
+        crate::write_atomic(&self.path, |file| {
+            // Players expect `.m3u8` files to declare themselves, even without any EXTINF lines
+            let is_m3u8 = self.path.extension().is_some_and(|x| x == "m3u8");
+            let has_extinf = self.preambles.iter().any(|x| x.extinf.is_some());
+            if self.is_extended || is_m3u8 || has_extinf {
+                write!(file, "#EXTM3U{}", eol)?;
+            }
+            for (track, preamble) in self.tracks.iter().zip(&self.preambles) {
+                let (before, after) = preamble.split_comments();
+                for comment in before {
+                    write!(file, "{}{}", comment, eol)?;
+                }
+                if let Some(extinf) = &preamble.extinf {
+                    write!(file, "{}{}", extinf, eol)?;
+                }
+                for comment in after {
+                    write!(file, "{}{}", comment, eol)?;
+                }
+                write!(file, "{}{}", track.path, eol)?;
+            }
+            for comment in &self.trailer {
+                write!(file, "{}{}", comment, eol)?;
+            }
+            Ok(())
+        })?;
+        self.mtime = Some(fs::metadata(&self.path)?.modified()?);
         self.is_modified = false;
         Ok(())
     }
@@ -249,14 +838,13 @@ impl TracksFile for Playlist {
         }
 
         self.tracks.remove(index);
+        let preamble = self.preambles.remove(index);
+        self.keep_comments(preamble.comments, index);
 
         // Shift all higher indices down by one
-        for track in &self.tracks[index..] {
-            for i in self.tracks_map.get_mut(track).unwrap() {
-                assert!(*i != index);
-                if *i > index {
-                    *i -= 1;
-                }
+        for indices in self.tracks_map.values_mut() {
+            for i in indices.iter_mut().filter(|i| **i > index) {
+                *i -= 1;
             }
         }
         self.is_modified = true;
@@ -267,13 +855,80 @@ impl TracksFile for Playlist {
         if !self.tracks_map.contains_key(track) {
             return 0;
         }
-        let mut indices = self.tracks_map[track].clone();
-        indices.sort_unstable();
-        for index in indices.iter().rev() {
-            self.remove_at(*index);
+        let indices = self.tracks_map[track].clone();
+        self.remove_many(&indices)
+    }
+
+    fn swap_remove_at(&mut self, index: usize) {
+        if index >= self.tracks.len() {
+            warn!("Out-of-bounds swap_remove_at requested (index: {}, len: {})", index, self.tracks.len());
+            return;
+        }
+
+        // Remove index pointing at the given track from `tracks_map`
+        let track = &self.tracks[index];
+        // If either unwrap here fails, it means `tracks_map` got corrupt somehow
+        let map_index = self.tracks_map[track].iter().position(|&x| x == index).unwrap();
+        self.tracks_map.get_mut(track).unwrap().remove(map_index);
+        if self.tracks_map[track].is_empty() {
+            self.tracks_map.remove(track);
+        }
+
+        // Repoint the last track's index to the vacated slot
+        let last = self.tracks.len() - 1;
+        if index != last {
+            let indices = self.tracks_map.get_mut(&self.tracks[last]).unwrap();
+            let map_index = indices.iter().position(|&x| x == last).unwrap();
+            indices.remove(map_index);
+            let pos = indices.partition_point(|&i| i < index);
+            indices.insert(pos, index);
+        }
+
+        self.tracks.swap_remove(index);
+        let preamble = self.preambles.swap_remove(index);
+        self.keep_comments(preamble.comments, index);
+        self.is_modified = true;
+        debug_assert!(self.verify_integrity());
+    }
+
+    fn remove_many(&mut self, indices: &[usize]) -> usize {
+        let mut is_removed = vec![false; self.tracks.len()];
+        let mut n_removed = 0usize;
+        for &index in indices {
+            if index >= self.tracks.len() {
+                warn!("Out-of-bounds remove_many requested (index: {}, len: {})", index, self.tracks.len());
+                continue;
+            }
+            if !is_removed[index] {
+                is_removed[index] = true;
+                n_removed += 1;
+            }
+        }
+        if n_removed == 0 {
+            return 0;
+        }
+
+        let tracks = std::mem::take(&mut self.tracks);
+        let preambles = std::mem::take(&mut self.preambles);
+        // Comments of removed tracks are passed on to the next remaining track
+        let mut orphaned_comments = Vec::new();
+        for ((track, mut preamble), is_removed) in tracks.into_iter().zip(preambles).zip(is_removed) {
+            if is_removed {
+                orphaned_comments.append(&mut preamble.comments);
+                continue;
+            }
+            if !orphaned_comments.is_empty() {
+                orphaned_comments.append(&mut preamble.comments);
+                preamble.comments = std::mem::take(&mut orphaned_comments);
+            }
+            self.tracks.push(track);
+            self.preambles.push(preamble);
         }
+        let len = self.tracks.len();
+        self.keep_comments(orphaned_comments, len);
+        self.rebuild_tracks_map();
         self.is_modified = true;
-        indices.len()
+        n_removed
     }
 
     fn bulk_rename(&mut self, edits: &HashMap<Track, Utf8PathBuf>) -> usize {
@@ -292,3 +947,24 @@ impl TracksFile for Playlist {
         n_changed
     }
 }
+
+/// Finds tracks which no longer exist on disk, e.g. after moving files around the music directory.
+/// Returns a map of playlist paths to the invalid tracks they contain, in order of appearance.
+/// Playlists are keyed by path rather than name, since e.g. `foo.m3u` and `foo.m3u8` share one.
+/// Playlists with no invalid tracks are omitted.
+pub fn find_broken_references() -> Result<HashMap<Utf8PathBuf, Vec<Track>>> {
+    let playlists = Playlist::iter().ok_or_else(|| io::Error::other(
+        format!("Failed to list the playlists directory '{}'", Playlist::playlist_dir())
+    ))?;
+    let mut broken = HashMap::new();
+    for playlist in playlists {
+        let tracks = playlist.tracks()
+            .filter(|x| !x.exists())
+            .cloned()
+            .collect::<Vec<Track>>();
+        if !tracks.is_empty() {
+            broken.insert(playlist.path, tracks);
+        }
+    }
+    Ok(broken)
+}
@@ -1,4 +1,9 @@
-use camino::{Utf8Path, Utf8PathBuf};
+use crate::music_dir;
+use crate::error::{MusicToolsError, Result};
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+use id3::{Tag, TagLike};
+use std::collections::HashSet;
+use std::time::Duration;
 
 /// A track in a playlist.
 ///
@@ -12,10 +17,158 @@ pub struct Track {
     pub path: Utf8PathBuf,
 }
 
+/// Basic metadata of a track, read from its ID3v2 tag.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct TrackMetadata {
+    /// The track title (TIT2).
+    pub title: Option<String>,
+
+    /// The track artist (TPE1).
+    pub artist: Option<String>,
+
+    /// The album title (TALB).
+    pub album: Option<String>,
+
+    /// The track length (TLEN).
+    pub duration: Option<Duration>,
+}
+
 impl Track {
     pub fn new<T: AsRef<Utf8Path>>(fpath: T) -> Self {
         Track {
             path: Utf8PathBuf::from(fpath.as_ref()),
         }
     }
+
+    /// Creates a track from an absolute path to an audio file inside `MUSIC_DIR`, storing it
+    /// relative to `MUSIC_DIR` (the form used by playlists and playcounts).
+    /// This is the inverse of `absolute_path()`.
+    ///
+    /// Returns an error if the path is relative or lies outside of `MUSIC_DIR`.
+    pub fn from_absolute<T: AsRef<Utf8Path>>(fpath: T) -> Result<Self> {
+        let fpath = fpath.as_ref();
+        if !fpath.is_absolute() {
+            return Err(MusicToolsError::InvalidPath(format!("'{}' is not absolute", fpath)));
+        }
+        match fpath.strip_prefix(music_dir()) {
+            Ok(path) => Ok(Track::new(path)),
+            Err(_) => Err(MusicToolsError::InvalidPath(format!("'{}' is outside of '{}'", fpath, music_dir()))),
+        }
+    }
+
+    /// Returns a copy of the track with its path relative to `base`. Tracks which are already
+    /// relative, or lie outside of `base`, are returned unchanged.
+    pub fn to_relative(&self, base: &Utf8Path) -> Track {
+        match self.path.strip_prefix(base) {
+            Ok(rel_path) if self.path.is_absolute() => Track::new(rel_path),
+            _ => self.clone(),
+        }
+    }
+
+    /// Returns a copy of the track with its path prefixed with `base`. Tracks which are already
+    /// absolute are returned unchanged.
+    pub fn to_absolute(&self, base: &Utf8Path) -> Track {
+        Track::new(base.join(&self.path))
+    }
+
+    /// Returns a score between 0 and 1 of how likely two tracks refer to the same audio file,
+    /// e.g. after it was moved or renamed. File names weigh the most, followed by the directory
+    /// components (artist, album) they share. Comparisons are case-insensitive and ignore file
+    /// extensions.
+    pub fn similarity(&self, other: &Track) -> f32 {
+        fn bigrams(str: &str) -> Vec<(char, char)> {
+            let chars = str.to_lowercase().chars().collect::<Vec<char>>();
+            chars.windows(2).map(|x| (x[0], x[1])).collect()
+        }
+        fn dirs(path: &Utf8Path) -> HashSet<String> {
+            path.parent()
+                .map(|x| x.components().map(|y| y.as_str().to_lowercase()).collect())
+                .unwrap_or_default()
+        }
+
+        // Dice coefficient of file name character bigrams
+        let stem_a = self.path.file_stem().unwrap_or_default();
+        let stem_b = other.path.file_stem().unwrap_or_default();
+        let name_score = if stem_a.eq_ignore_ascii_case(stem_b) {
+            1.0
+        } else {
+            let a = bigrams(stem_a);
+            let mut b = bigrams(stem_b);
+            let total = a.len() + b.len();
+            let mut n_shared = 0usize;
+            for bigram in a {
+                if let Some(pos) = b.iter().position(|&x| x == bigram) {
+                    b.swap_remove(pos);
+                    n_shared += 1;
+                }
+            }
+            if total == 0 { 0.0 } else { 2.0 * n_shared as f32 / total as f32 }
+        };
+
+        // Jaccard index of directory components
+        let dirs_a = dirs(&self.path);
+        let dirs_b = dirs(&other.path);
+        let n_union = dirs_a.union(&dirs_b).count();
+        let dir_score = match n_union {
+            0 => 1.0,
+            n => dirs_a.intersection(&dirs_b).count() as f32 / n as f32,
+        };
+
+        0.7 * name_score + 0.3 * dir_score
+    }
+
+    /// Returns a canonical form of the track, for comparisons that should not depend on the exact
+    /// spelling of the path. Redundant separators and `.` components are removed, and if
+    /// `fold_case` is true, the path is converted to lowercase (for case-insensitive filesystems).
+    ///
+    /// Note that `Track` equality and hashing are always byte-exact; this is opt-in.
+    pub fn normalized(&self, fold_case: bool) -> Track {
+        let path = self.path.components()
+            .filter(|x| *x != Utf8Component::CurDir)
+            .collect::<Utf8PathBuf>();
+        if fold_case {
+            Track::new(path.as_str().to_lowercase())
+        } else {
+            Track::new(path)
+        }
+    }
+
+    /// Returns the absolute path to the audio file. Relative paths are resolved under
+    /// `MUSIC_DIR`, while absolute paths are returned as-is.
+    pub fn absolute_path(&self) -> Utf8PathBuf {
+        if self.path.is_absolute() {
+            self.path.clone()
+        } else {
+            music_dir().join(&self.path)
+        }
+    }
+
+    /// Returns whether the audio file exists on disk (see `absolute_path()`).
+    pub fn exists(&self) -> bool {
+        self.absolute_path().exists()
+    }
+
+    /// Reads basic metadata from the audio file's ID3v2 tag.
+    ///
+    /// Nothing is cached, i.e. every call reads the file anew. A file without a tag yields empty
+    /// metadata, rather than an error.
+    pub fn metadata(&self) -> Result<TrackMetadata> {
+        let path = self.absolute_path();
+        let tag = match id3::no_tag_ok(Tag::read_from_path(&path))? {
+            Some(tag) => tag,
+            None => return Ok(TrackMetadata::default()),
+        };
+        Ok(TrackMetadata {
+            title: tag.title().map(String::from),
+            artist: tag.artist().map(String::from),
+            album: tag.album().map(String::from),
+            duration: tag.duration().map(|x| Duration::from_millis(x.into())),
+        })
+    }
+}
+
+impl std::fmt::Display for Track {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.path)
+    }
 }
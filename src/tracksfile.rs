@@ -1,7 +1,34 @@
 use crate::track::Track;
-use anyhow::Result;
+use crate::error::{MusicToolsError, Result};
 use camino::{Utf8Path, Utf8PathBuf};
-use std::collections::HashMap;
+use log::warn;
+use std::collections::{HashMap, HashSet};
+
+/// A uniform overview of a tracks file (see `TracksFile::summary()`).
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TracksFileSummary {
+    /// The path to the text file.
+    pub path: Utf8PathBuf,
+
+    /// The number of tracks, counting repetitions.
+    pub n_tracks: usize,
+
+    /// The number of unique tracks.
+    pub n_unique: usize,
+
+    /// Whether the object has been modified since the last `write`.
+    pub is_modified: bool,
+}
+
+/// The outcome of `TracksFile::map_paths()`.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct PathMapping {
+    /// The number of changed tracks (duplicate paths are counted).
+    pub n_changed: usize,
+
+    /// The number of resulting paths onto which several distinct tracks were mapped.
+    pub n_collisions: usize,
+}
 
 /// A trait for dealing with text files containing a list of tracks.
 /// This description fits m3u playlists, but also more esoteric custom formats.
@@ -23,6 +50,13 @@ pub trait TracksFile {
     /// Works like `open()` if the file exists, and like `new()` if it doesn't.
     fn open_or_new<T: AsRef<Utf8Path>>(fpath: T) -> Result<Self> where Self: Sized;
 
+    /// Re-reads the object from its file, discarding all unsaved modifications.
+    /// If the file cannot be read, an error is returned and the object is left untouched.
+    fn reload(&mut self) -> Result<()> where Self: Sized {
+        *self = Self::open(self.path())?;
+        Ok(())
+    }
+
     /// Returns an iterator over all objects.
     /// The objects are not all loaded into memory at once; they are created on-demand only.
     fn iter() -> Option<impl Iterator<Item = Self>> where Self: Sized;
@@ -41,29 +75,177 @@ pub trait TracksFile {
     /// Returns whether a track appears in the object.
     fn contains(&self, track: &Track) -> bool;
 
+    /// Works like `contains()`, but takes a bare path. Like `Track` equality, the comparison is
+    /// byte-exact (see `Track::normalized()`).
+    fn contains_path(&self, path: &Utf8Path) -> bool {
+        self.contains(&Track::new(path))
+    }
+
+    /// Returns all unique tracks matching a predicate, in order of their first appearance.
+    fn find<F: Fn(&Track) -> bool>(&self, pred: F) -> Vec<&Track> {
+        let mut seen = HashSet::new();
+        self.tracks()
+            .filter(|&x| pred(x) && seen.insert(x))
+            .collect()
+    }
+
+    /// Returns all unique tracks whose path contains `needle`, in order of their first appearance.
+    fn find_path_contains(&self, needle: &str) -> Vec<&Track> {
+        self.find(|x| x.path.as_str().contains(needle))
+    }
+
     /// Returns a vector of indices at which the given track occurs.
     /// The indices are sorted in ascending order, i.e. the order in which they appear in the
     /// object.
     fn track_positions(&self, track: &Track) -> Option<&Vec<usize>>;
 
+    /// Works like `track_positions()`, but takes a bare path.
+    fn track_positions_for_path(&self, path: &Utf8Path) -> Option<&Vec<usize>> {
+        self.track_positions(&Track::new(path))
+    }
+
+    /// Returns the number of times a track appears in the object (0 if absent).
+    ///
+    /// Note that for playcounts, this is the number of entries referring to the track, not the
+    /// number of times it was played.
+    fn count_occurrences(&self, track: &Track) -> usize {
+        self.track_positions(track).map_or(0, |x| x.len())
+    }
+
     /// Returns whether the object has been modified since the last `write`.
     fn is_modified(&self) -> bool;
 
+    /// Returns an overview of the object, in a form common to all implementors.
+    fn summary(&self) -> TracksFileSummary {
+        TracksFileSummary {
+            path: self.path().clone(),
+            n_tracks: self.tracks().count(),
+            n_unique: self.tracks_unique().count(),
+            is_modified: self.is_modified(),
+        }
+    }
+
     /// Overwrites the text file to reflect the current object state.
     fn write(&mut self) -> Result<()>;
 
+    /// Calls `write()` only if the object has been modified since the last write.
+    /// Returns whether a write took place.
+    fn write_if_modified(&mut self) -> Result<bool> {
+        if !self.is_modified() {
+            return Ok(false);
+        }
+        self.write()?;
+        Ok(true)
+    }
+
     /// Removes a track from the object, by index.
     fn remove_at(&mut self, index: usize);
 
-    /// Removes all (if any) occurrences of a track from the object.
+    /// Removes a track from the object, by index, by swapping the last track into its place.
+    /// This is O(1), unlike `remove_at()`, but changes the order of tracks.
+    fn swap_remove_at(&mut self, index: usize);
+
+    /// Removes many tracks at once, by indices. This is much faster than calling `remove_at()`
+    /// repeatedly, as the remaining tracks are shifted and reindexed in a single pass.
+    /// Out-of-bounds and repeated indices are ignored. If nothing is removed, the object is not
+    /// marked as modified.
+    /// Returns the number of tracks removed.
+    fn remove_many(&mut self, indices: &[usize]) -> usize;
+
+    /// Removes all (if any) occurrences of a track from the object. If the track does not appear,
+    /// this is a no-op and the object is not marked as modified.
     /// Returns the number of tracks removed.
     fn remove_all(&mut self, track: &Track) -> usize;
 
-    /// Modify the path of a subset of tracks at the same time.
+    /// Removes all duplicate tracks, leaving only the first occurrence of each.
+    /// Returns the number of tracks removed.
+    fn dedup(&mut self) -> usize where Self: Sized {
+        self.dedup_with(|_, _, _| ())
+    }
+
+    /// Removes all duplicate tracks, leaving only the first occurrence of each. Before removal,
+    /// `combine` is called for each duplicated track with the index of its first occurrence and
+    /// the indices of the remaining ones, e.g. to carry over some data from the latter.
+    /// Returns the number of tracks removed.
+    fn dedup_with<F: FnMut(&mut Self, usize, &[usize])>(&mut self, mut combine: F) -> usize where Self: Sized {
+        let groups = self.tracks_unique()
+            .filter_map(|x| self.track_positions(x))
+            .filter(|x| x.len() > 1)
+            .cloned()
+            .collect::<Vec<Vec<usize>>>();
+        let mut indices = Vec::new();
+        for pos in &groups {
+            combine(self, pos[0], &pos[1..]);
+            indices.extend_from_slice(&pos[1..]);
+        }
+        self.remove_many(&indices)
+    }
+
+    /// Modify the path of a subset of tracks at the same time. Edits targeting tracks which do not
+    /// appear in the object are skipped.
     ///
     /// Ensures safe handling of tricky scenarios like renaming A to B and B to A, or renaming A to
     /// B and then B to C, which in a naive implementation might cause A to end up as C.
     ///
+    /// This is the primitive each implementor provides; callers should use `rename_tracks()`.
+    ///
     /// Returns the number of changed tracks (duplicate paths are counted).
     fn bulk_rename(&mut self, edits: &HashMap<Track, Utf8PathBuf>) -> usize;
+
+    /// Modify the path of a subset of tracks at the same time (see `bulk_rename()`). This is the
+    /// entry point for renaming tracks in any tracks file. If `strict` is true, edits targeting
+    /// tracks which do not appear in the object are treated as an error, otherwise they are
+    /// silently skipped. In case of an error, no tracks are renamed.
+    ///
+    /// Returns the number of changed tracks (duplicate paths are counted).
+    fn rename_tracks(&mut self, edits: &HashMap<Track, Utf8PathBuf>, strict: bool) -> Result<usize> where Self: Sized {
+        if strict {
+            let missing = edits.keys()
+                .filter(|x| !self.contains(x))
+                .cloned()
+                .collect::<Vec<Track>>();
+            if !missing.is_empty() {
+                return Err(MusicToolsError::MissingTrack { path: self.path().clone(), tracks: missing });
+            }
+        }
+        Ok(self.bulk_rename(edits))
+    }
+
+    /// Applies a transformation function to the path of every track.
+    ///
+    /// This is a generalization of `bulk_rename()`, useful for changing extensions, stripping
+    /// prefixes, etc. If the transformation causes distinct tracks to end up with the same path,
+    /// a warning is logged for each such path (the tracks are merged as a result).
+    ///
+    /// Returns the number of changed tracks and the number of such collisions.
+    fn map_paths<F: Fn(&Utf8Path) -> Utf8PathBuf>(&mut self, f: F) -> PathMapping where Self: Sized {
+        let mut edits = HashMap::<Track, Utf8PathBuf>::new();
+        let mut results = HashMap::<Utf8PathBuf, usize>::new();
+        for track in self.tracks_unique() {
+            let new_path = f(&track.path);
+            if new_path != track.path {
+                edits.insert(track.clone(), new_path.clone());
+            }
+            *results.entry(new_path).or_default() += 1;
+        }
+        let mut n_collisions = 0usize;
+        for (path, n) in results.iter().filter(|(_, &n)| n > 1) {
+            warn!("{} distinct tracks in '{}' were mapped to the same path '{}'", n, self.path(), path);
+            n_collisions += 1;
+        }
+        PathMapping { n_changed: self.bulk_rename(&edits), n_collisions }
+    }
+}
+
+/// Records that `track` occurs at `index`, in a track index of the form used by `TracksFile`
+/// implementors (a map of tracks to the ascending positions at which they occur).
+/// `index` must be greater than all indices already recorded, i.e. the track is being appended.
+pub(crate) fn index_track(tracks_map: &mut HashMap<Track, Vec<usize>>, track: &Track, index: usize) {
+    match tracks_map.get_mut(track) {
+        Some(indices) => {
+            debug_assert!(indices.last().is_some_and(|&i| i < index));
+            indices.push(index);
+        },
+        None => { tracks_map.insert(track.clone(), vec![index]); },
+    }
 }
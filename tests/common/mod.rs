@@ -0,0 +1,64 @@
+//! Shared scaffolding for integration tests.
+//!
+//! The library resolves its directories once per process, so every test binary points them at
+//! a single temporary music directory, set up on first use. Tests within a binary run in
+//! parallel, so each of them should use its own fixture file names.
+#![allow(dead_code)]
+
+use camino::{Utf8Path, Utf8PathBuf};
+use music_tools::playlist::TracksFile;
+use std::fs;
+use std::sync::OnceLock;
+
+/// Returns the temporary music directory, creating it and pointing the library at it on first
+/// use. This must be called before any library function that resolves a directory.
+pub fn music_dir() -> &'static Utf8Path {
+    static MUSIC_DIR: OnceLock<Utf8PathBuf> = OnceLock::new();
+    MUSIC_DIR.get_or_init(|| {
+        let tmp = Utf8PathBuf::from_path_buf(std::env::temp_dir()).expect("temp dir is not UTF-8");
+        let exe = std::env::current_exe().unwrap();
+        let name = exe.file_stem().unwrap().to_string_lossy();
+        let dir = tmp.join(format!("music-tools-{}-{}", name, std::process::id()));
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::create_dir_all(dir.join("Playlists")).unwrap();
+        fs::create_dir_all(dir.join(".playcount")).unwrap();
+        std::env::set_var("MUSIC_DIR", &dir);
+        std::env::set_var("MUSIC_PLAYLIST_DIR", dir.join("Playlists"));
+        std::env::set_var("MUSIC_PLAYCOUNT_DIR", dir.join(".playcount"));
+        assert_eq!(music_tools::music_dir(), dir);
+        dir
+    })
+}
+
+/// Writes a fixture file at a path relative to the music directory, creating parent directories
+/// as needed. Returns the absolute path.
+pub fn fixture<T: AsRef<Utf8Path>>(rel_path: T, contents: &str) -> Utf8PathBuf {
+    let path = music_dir().join(rel_path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+/// Creates empty audio files at paths relative to the music directory.
+pub fn audio_files(rel_paths: &[&str]) {
+    for rel_path in rel_paths {
+        fixture(rel_path, "");
+    }
+}
+
+/// Asserts that the track index of a tracks file agrees with its list of tracks, using only the
+/// public API (the library additionally checks its internal state with `debug_assert`).
+pub fn assert_integrity<T: TracksFile>(file: &T) {
+    let tracks = file.tracks().collect::<Vec<_>>();
+    for (i, track) in tracks.iter().enumerate() {
+        let positions = file.track_positions(track).expect("track missing from index");
+        assert!(positions.contains(&i), "index of '{}' lacks position {}", track, i);
+        assert!(positions.windows(2).all(|x| x[0] < x[1]), "positions of '{}' are not sorted", track);
+    }
+    let n_indexed = file.tracks_unique()
+        .map(|x| file.count_occurrences(x))
+        .sum::<usize>();
+    assert_eq!(n_indexed, tracks.len());
+}
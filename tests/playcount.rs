@@ -0,0 +1,288 @@
+mod common;
+
+use common::{assert_integrity, fixture, music_dir};
+use music_tools::error::MusicToolsError;
+use music_tools::playcount::{Playcount, SyncStrategy, TracksFile};
+use chrono::{DateTime, Utc};
+use music_tools::track::Track;
+use std::fs;
+use std::time::Instant;
+
+#[test]
+fn open_modify_write_reopen() {
+    music_dir();
+    let path = fixture(".playcount/roundtrip.tsv", "2\tA/a.mp3\n1\tB/b.mp3\n3\tA/a.mp3\n");
+
+    let mut pc = Playcount::open(&path).unwrap();
+    assert_eq!(pc.plays_for(&Track::new("A/a.mp3")), 5);
+    assert_eq!(pc.entries().map(|x| x.line()).collect::<Vec<_>>(), [Some(1), Some(2), Some(3)]);
+    assert_integrity(&pc);
+
+    assert_eq!(pc.merge_duplicates(), 1);
+    pc.add_play(&Track::new("C/c.mp3"), 4);
+    assert_integrity(&pc);
+    pc.write().unwrap();
+
+    let pc = Playcount::open(&path).unwrap();
+    assert_eq!(pc.total_plays(), 10);
+    assert_eq!(pc.tracks().count(), 3);
+    assert_eq!(pc.top_n(1), [(&Track::new("A/a.mp3"), 5)]);
+    assert_integrity(&pc);
+}
+
+#[test]
+fn append_play_keeps_file_in_sync() {
+    music_dir();
+    // No final newline, as after editing by hand
+    let path = fixture(".playcount/append.tsv", "1\tA/a.mp3");
+    let ts = DateTime::from_timestamp(1700000000, 0).unwrap();
+
+    let mut pc = Playcount::open(&path).unwrap();
+    pc.append_play(&Track::new("A/a.mp3"), 2, None).unwrap();
+    pc.append_play(&Track::new("B/b.mp3"), 1, Some(ts)).unwrap();
+    assert!(!pc.is_modified());
+    assert_integrity(&pc);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "1\tA/a.mp3\n2\tA/a.mp3\n1\tB/b.mp3\t1700000000\n");
+
+    let reopened = Playcount::open_strict(&path).unwrap();
+    assert_eq!(reopened.entries().collect::<Vec<_>>(), pc.entries().collect::<Vec<_>>());
+    assert_eq!(reopened.plays_since(ts), 1);
+}
+
+#[test]
+fn subtract_play_only_touches_first_entry() {
+    music_dir();
+    let path = fixture(".playcount/subtract.tsv", "1\tA/a.mp3\n2\tB/b.mp3\n2\tA/a.mp3\n");
+
+    let mut pc = Playcount::open(&path).unwrap();
+    assert!(matches!(
+        pc.subtract_play(&Track::new("A/a.mp3"), 2),
+        Err(MusicToolsError::NotEnoughPlays { available: 1, .. }),
+    ));
+    assert!(!pc.is_modified());
+
+    pc.subtract_play(&Track::new("B/b.mp3"), 1).unwrap();
+    assert_eq!(pc.plays_for(&Track::new("B/b.mp3")), 1);
+    pc.subtract_play(&Track::new("A/a.mp3"), 1).unwrap();
+    assert_eq!(pc.entries().map(|x| x.count).collect::<Vec<_>>(), [1, 2]);
+    assert_eq!(pc.count_occurrences(&Track::new("A/a.mp3")), 1);
+    assert_integrity(&pc);
+
+    assert!(matches!(
+        pc.subtract_play(&Track::new("Z/z.mp3"), 1),
+        Err(MusicToolsError::MissingTrack { .. }),
+    ));
+}
+
+#[test]
+fn subtract_play_undoes_add_play() {
+    music_dir();
+    let path = fixture(".playcount/undo.tsv", "1\tA/a.mp3\t1700000000\n3\tA/a.mp3\n");
+
+    let mut pc = Playcount::open(&path).unwrap();
+    pc.add_play(&Track::new("A/a.mp3"), 1);
+    pc.subtract_play(&Track::new("A/a.mp3"), 1).unwrap();
+    assert_eq!(pc.entries().map(|x| (x.count, x.timestamp.is_some())).collect::<Vec<_>>(), [(1, true), (3, false)]);
+
+    // Timestamped entries alone cannot be subtracted from
+    let path = fixture(".playcount/undo-timed.tsv", "1\tA/a.mp3\t1700000000\n");
+    let mut pc = Playcount::open(&path).unwrap();
+    assert!(matches!(
+        pc.subtract_play(&Track::new("A/a.mp3"), 1),
+        Err(MusicToolsError::NotEnoughPlays { available: 0, .. }),
+    ));
+}
+
+#[test]
+fn remove_at_before_later_duplicates() {
+    music_dir();
+    let contents = "1\tA/a.mp3\n1\tB/b.mp3\t2023-01-01\n1\tB/b.mp3\t2023-02-01\n";
+    let path = fixture(".playcount/later-duplicates.tsv", contents);
+
+    let mut pc = Playcount::open(&path).unwrap();
+    pc.subtract_play(&Track::new("A/a.mp3"), 1).unwrap();
+    assert_eq!(pc.track_positions(&Track::new("B/b.mp3")), Some(&vec![0, 1]));
+    assert_integrity(&pc);
+}
+
+#[test]
+fn invalid_counts() {
+    music_dir();
+    let too_large = format!("{}0", usize::MAX);
+    let contents = format!("0\tA/a.mp3\n-1\tB/b.mp3\n{}\tC/c.mp3\n1\tD/d.mp3\n", too_large);
+    let path = fixture(".playcount/counts.tsv", &contents);
+
+    let (mut pc, errors) = Playcount::open_lossy(&path).unwrap();
+    assert_eq!(errors.iter().map(|x| x.0).collect::<Vec<_>>(), [2, 3]);
+    assert!(Playcount::open_strict(&path).is_err());
+
+    assert_eq!(pc.drop_zero_counts(), 1);
+    assert_eq!(pc.tracks().collect::<Vec<_>>(), [&Track::new("D/d.mp3")]);
+    assert_integrity(&pc);
+}
+
+#[test]
+fn sync_strategies() {
+    music_dir();
+    let local_path = fixture(".playcount/sync-local.tsv", "3\tA/a.mp3\n1\tB/b.mp3\n");
+    let remote_path = fixture(".playcount/sync-remote.tsv", "1\tA/a.mp3\n4\tB/b.mp3\n2\tC/c.mp3\n");
+    let remote = Playcount::open(&remote_path).unwrap();
+
+    let totals = |strategy| {
+        let mut pc = Playcount::open(&local_path).unwrap();
+        pc.sync(&remote, strategy);
+        assert_integrity(&pc);
+        ["A/a.mp3", "B/b.mp3", "C/c.mp3"].map(|x| pc.plays_for(&Track::new(x)))
+    };
+    assert_eq!(totals(SyncStrategy::Max), [3, 4, 2]);
+    assert_eq!(totals(SyncStrategy::Sum), [4, 5, 2]);
+    assert_eq!(totals(SyncStrategy::PreferLocal), [3, 1, 2]);
+}
+
+#[test]
+fn timestamps_survive_merging_and_writing() {
+    music_dir();
+    let contents = "1\tA/a.mp3\t2023-06-01\n1\tA/a.mp3\t2023-06-20\n1\tA/a.mp3\t2023-06-20\n2\tB/b.mp3\n";
+    let path = fixture(".playcount/timestamps.tsv", contents);
+    let cutoff = "2023-06-10T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+
+    let mut pc = Playcount::open(&path).unwrap();
+    assert_eq!(pc.plays_since(cutoff), 2);
+    assert_eq!(pc.merge_duplicates(), 1);
+    assert_eq!(pc.plays_since(cutoff), 2);
+    assert_integrity(&pc);
+
+    // Original ISO-8601 dates are written back as they were
+    pc.write().unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "1\tA/a.mp3\t2023-06-01\n2\tA/a.mp3\t2023-06-20\n2\tB/b.mp3\n",
+    );
+
+    let mut other = Playcount::new("other.tsv").unwrap();
+    other.merge_from(&pc);
+    assert_eq!(other.tracks().count(), 3);
+    assert_eq!(other.plays_since(cutoff), 2);
+}
+
+#[test]
+fn dedup_collapses_timestamps() {
+    music_dir();
+    let contents = "1\tA/a.mp3\t2023-01-01\n2\tA/a.mp3\t2023-02-01\n3\tA/a.mp3\n";
+    let path = fixture(".playcount/dedup.tsv", contents);
+
+    let mut pc = Playcount::open(&path).unwrap();
+    assert_eq!(pc.merge_duplicates(), 0);
+    assert_eq!(pc.dedup(), 2);
+    assert_eq!(pc.count_occurrences(&Track::new("A/a.mp3")), 1);
+    assert_eq!(pc.plays_for(&Track::new("A/a.mp3")), 6);
+    assert!(pc.is_modified());
+    assert_integrity(&pc);
+}
+
+#[test]
+fn dedup_with_custom_combiner() {
+    music_dir();
+    let path = fixture(".playcount/dedup-with.tsv", "2\tA/a.mp3\n1\tB/b.mp3\n5\tA/a.mp3\n3\tA/a.mp3\n");
+
+    // Keep the highest count of each track instead of the sum
+    let mut pc = Playcount::open(&path).unwrap();
+    let n_removed = pc.dedup_with(|pc, first, dupes| {
+        let counts = pc.entries().map(|x| x.count).collect::<Vec<usize>>();
+        let max = dupes.iter().map(|&i| counts[i]).fold(counts[first], usize::max);
+        *pc.entries_mut().nth(first).unwrap() = max;
+    });
+    assert_eq!(n_removed, 2);
+    assert_eq!(pc.entries().map(|x| (x.track.path.as_str(), x.count)).collect::<Vec<_>>(), [("A/a.mp3", 5), ("B/b.mp3", 1)]);
+    assert_integrity(&pc);
+}
+
+#[test]
+fn paths_with_tabs() {
+    music_dir();
+    let contents = "1\tA/odd\tname.mp3\n2\tB/b.mp3\t1700000000\n3\tArtist\t1999\n";
+    let path = fixture(".playcount/tabs.tsv", contents);
+
+    // Numbers shorter than 10 digits are part of the path, rather than a Unix timestamp
+    let mut pc = Playcount::open_strict(&path).unwrap();
+    let tracks = pc.tracks().map(|x| x.path.as_str()).collect::<Vec<_>>();
+    assert_eq!(tracks, ["A/odd\tname.mp3", "B/b.mp3", "Artist\t1999"]);
+    assert_eq!(pc.entries().map(|x| x.timestamp.is_some()).collect::<Vec<_>>(), [false, true, false]);
+
+    // Old timestamps are written in a format which reads back unambiguously
+    let old = "1999-12-31T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+    pc.append_play(&Track::new("C/c.mp3"), 1, Some(old)).unwrap();
+    let reopened = Playcount::open_strict(&path).unwrap();
+    assert_eq!(reopened.entries().last().unwrap().timestamp, Some(old));
+    assert!(fs::read_to_string(&path).unwrap().ends_with("1\tC/c.mp3\t1999-12-31T00:00:00Z\n"));
+}
+
+#[test]
+fn emptied_playcount_writes_empty_file() {
+    music_dir();
+    let path = fixture(".playcount/emptied.tsv", "1\tA/a.mp3\n2\tA/a.mp3\n");
+
+    let mut pc = Playcount::open(&path).unwrap();
+    assert_eq!(pc.remove_all(&Track::new("A/a.mp3")), 2);
+    pc.write().unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "");
+
+    let pc = Playcount::open_strict(&path).unwrap();
+    assert_eq!(pc.entries().count(), 0);
+}
+
+#[test]
+fn remove_many_is_linear() {
+    music_dir();
+    // Removes every other entry out of `n`, returning the best time out of a few runs
+    let time_removal = |n: usize| {
+        let contents = (0..n).map(|i| format!("1\tBulk/{}.mp3\n", i)).collect::<String>();
+        let path = fixture(format!(".playcount/bulk-{}.tsv", n), &contents);
+        let indices = (0..n).step_by(2).collect::<Vec<usize>>();
+        (0..3).map(|_| {
+            let mut pc = Playcount::open(&path).unwrap();
+            let start = Instant::now();
+            assert_eq!(pc.remove_many(&indices), n / 2);
+            let elapsed = start.elapsed();
+
+            assert_eq!(pc.entries().count(), n / 2);
+            assert_eq!(pc.entries().next().unwrap().track, Track::new("Bulk/1.mp3"));
+            assert_integrity(&pc);
+            elapsed
+        }).min().unwrap()
+    };
+
+    // Quadrupling the size should roughly quadruple the time, whereas removing entries one by
+    // one would make it 16 times slower
+    let ratio = time_removal(80_000).as_secs_f64() / time_removal(20_000).as_secs_f64();
+    assert!(ratio < 10.0, "quadrupling the size made remove_many {:.1}x slower", ratio);
+}
+#[test]
+fn noop_removal_keeps_unmodified() {
+    music_dir();
+    let path = fixture(".playcount/noop.tsv", "1\tA/a.mp3\n");
+
+    let mut pc = Playcount::open(&path).unwrap();
+    assert_eq!(pc.remove_all(&Track::new("Z/z.mp3")), 0);
+    assert!(!pc.is_modified());
+}
+
+#[test]
+fn to_playlist_follows_ranking() {
+    music_dir();
+    let contents = "1\tA/a.mp3\n3\tB/b.mp3\n2\tA/a.mp3\n1\tC/c.mp3\n2\tD/d.mp3\n";
+    let path = fixture(".playcount/ranking.tsv", contents);
+    let pc = Playcount::open(&path).unwrap();
+
+    let mut pl = pc.to_playlist(music_dir().join("Playlists/top.m3u"), 3).unwrap();
+    let ranking = pc.top_n(3).into_iter().map(|x| x.0.clone()).collect::<Vec<Track>>();
+    assert_eq!(pl.tracks().cloned().collect::<Vec<Track>>(), ranking);
+    assert_integrity(&pl);
+
+    // Both entries of 'A/a.mp3' collapse into a single line, ranked first by their sum
+    pl.write().unwrap();
+    assert_eq!(
+        fs::read_to_string(music_dir().join("Playlists/top.m3u")).unwrap(),
+        "A/a.mp3\nB/b.mp3\nD/d.mp3\n",
+    );
+}
@@ -0,0 +1,312 @@
+mod common;
+
+use common::{assert_integrity, audio_files, fixture, music_dir};
+use camino::Utf8PathBuf;
+use music_tools::playlist::{find_broken_references, ExtInfRefresh, LineEnding, PathMapping, Playlist, TracksFile};
+use music_tools::error::MusicToolsError;
+use music_tools::track::Track;
+use std::collections::HashMap;
+use std::fs;
+
+#[test]
+fn open_modify_write_reopen() {
+    music_dir();
+    let path = fixture("Playlists/roundtrip.m3u", "A/a.mp3\nB/b.mp3\nA/a.mp3\n");
+
+    let mut pl = Playlist::open(&path).unwrap();
+    assert_eq!(pl.name(), "roundtrip");
+    assert_eq!(pl.count_occurrences(&Track::new("A/a.mp3")), 2);
+    assert!(pl.contains_path("B/b.mp3".into()));
+    assert_eq!(pl.track_positions_for_path("A/a.mp3".into()), Some(&vec![0, 2]));
+    assert_integrity(&pl);
+
+    pl.add_track(Track::new("C/c.mp3"));
+    pl.remove_at(0);
+    assert!(pl.is_modified());
+    assert_integrity(&pl);
+    pl.write().unwrap();
+    assert!(!pl.is_modified());
+
+    let pl = Playlist::open(&path).unwrap();
+    let paths = pl.tracks().map(|x| x.path.as_str()).collect::<Vec<_>>();
+    assert_eq!(paths, ["B/b.mp3", "A/a.mp3", "C/c.mp3"]);
+    assert_integrity(&pl);
+}
+
+#[test]
+fn crlf_round_trip() {
+    music_dir();
+    let path = fixture("Playlists/crlf.m3u", "A/a.mp3\r\nB/b.mp3\r\n");
+
+    let mut pl = Playlist::open(&path).unwrap();
+    assert!(pl.contains(&Track::new("A/a.mp3")));
+    assert!(pl.tracks().all(|x| !x.path.as_str().contains('\r')));
+
+    pl.set_line_ending(LineEnding::Crlf);
+    pl.write().unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "A/a.mp3\r\nB/b.mp3\r\n");
+
+    // The line ending survives reloading
+    pl.reload().unwrap();
+    assert_eq!(pl.line_ending(), LineEnding::Crlf);
+    pl.add_track(Track::new("C/c.mp3"));
+    pl.write().unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "A/a.mp3\r\nB/b.mp3\r\nC/c.mp3\r\n");
+}
+
+#[test]
+fn extended_m3u_round_trip() {
+    music_dir();
+    let contents = "#EXTM3U\n#EXTINF:123,Artist - Title\nA/a.mp3\nB/b.mp3\n";
+    let path = fixture("Playlists/extended.m3u", contents);
+
+    let mut pl = Playlist::open(&path).unwrap();
+    let extinf = pl.extinf(&Track::new("A/a.mp3")).unwrap();
+    assert_eq!(extinf.title, "Artist - Title");
+    assert!(pl.extinf(&Track::new("B/b.mp3")).is_none());
+
+    pl.write().unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+
+    // Dangling and commented EXTINF lines stay where they were
+    let contents = "#EXTM3U\n#EXTINF:1,First\n# note\n#EXTINF:2,Second\n# about A\nA/a.mp3\n#EXTINF:3,Gone\n# end\n";
+    let path = fixture("Playlists/extended-dangling.m3u", contents);
+    let mut pl = Playlist::open(&path).unwrap();
+    assert_eq!(pl.extinf_at(0).unwrap().title, "Second");
+    pl.write().unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+}
+
+#[test]
+fn dedup_and_missing_tracks() {
+    audio_files(&["Dedup/present.mp3"]);
+    let path = fixture("Playlists/dedup.m3u", "Dedup/present.mp3\nDedup/gone.mp3\nDedup/present.mp3\n");
+
+    let mut pl = Playlist::open(&path).unwrap();
+    assert_eq!(pl.dedup(), 1);
+    assert_integrity(&pl);
+    assert_eq!(pl.missing_tracks(), [&Track::new("Dedup/gone.mp3")]);
+}
+
+#[test]
+fn split_into_chunks() {
+    music_dir();
+    let path = fixture("Playlists/split.m3u", "1.mp3\n2.mp3\n3.mp3\n4.mp3\n5.mp3\n");
+
+    let pl = Playlist::open(&path).unwrap();
+    let parts = pl.split(2);
+    assert_eq!(parts.len(), 3);
+    assert_eq!(parts[0].name(), "split.1");
+    assert_eq!(parts[2].path(), &music_dir().join("Playlists/split.3.m3u"));
+    assert_eq!(parts.iter().map(|x| x.tracks().count()).collect::<Vec<_>>(), [2, 2, 1]);
+    for part in &parts {
+        assert_integrity(part);
+    }
+}
+
+#[test]
+fn m3u8_declares_header() {
+    music_dir();
+    let path = fixture("Playlists/utf8.m3u8", "Björk/Homogenic/Jóga.mp3\n");
+
+    let mut pl = Playlist::open(&path).unwrap();
+    assert_eq!(pl.name(), "utf8");
+    assert!(pl.contains(&Track::new("Björk/Homogenic/Jóga.mp3")));
+
+    pl.add_track(Track::new("Sigur Rós/()/Untitled 1.mp3"));
+    pl.write().unwrap();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "#EXTM3U\nBjörk/Homogenic/Jóga.mp3\nSigur Rós/()/Untitled 1.mp3\n",
+    );
+}
+
+#[test]
+fn comments_round_trip() {
+    music_dir();
+    let contents = "# Road trip\nA/a.mp3\n\n# Side B\nB/b.mp3\n# the end\n";
+    let path = fixture("Playlists/comments.m3u", contents);
+
+    let mut pl = Playlist::open(&path).unwrap();
+    assert_eq!(pl.tracks().count(), 2);
+    pl.write().unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+
+    // Comments of a removed track stay in place
+    pl.remove_at(1);
+    pl.write().unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "# Road trip\nA/a.mp3\n\n# Side B\n# the end\n");
+}
+
+#[test]
+fn repeated_track_extinf_round_trip() {
+    music_dir();
+    let contents = "#EXTM3U\n#EXTINF:1,X\nA/a.mp3\nB/b.mp3\nA/a.mp3\n";
+    let path = fixture("Playlists/repeated-extinf.m3u", contents);
+
+    let mut pl = Playlist::open(&path).unwrap();
+    assert_eq!(pl.extinf_at(0).unwrap().title, "X");
+    assert!(pl.extinf_at(2).is_none());
+    pl.write().unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+
+    // Metadata follows its line through reordering
+    pl.reverse();
+    pl.write().unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "#EXTM3U\nA/a.mp3\nB/b.mp3\n#EXTINF:1,X\nA/a.mp3\n");
+}
+
+#[test]
+fn broken_references_after_move() {
+    audio_files(&["Moved/kept.mp3", "Moved/old.mp3"]);
+    fixture("Playlists/moved.m3u", "Moved/kept.mp3\nMoved/old.mp3\n");
+    fixture("Playlists/unmoved.m3u", "Moved/kept.mp3\n");
+    fs::rename(music_dir().join("Moved/old.mp3"), music_dir().join("Moved/new.mp3")).unwrap();
+
+    fixture("Playlists/moved.m3u8", "Moved/old.mp3\n");
+
+    let broken = find_broken_references().unwrap();
+    let playlists_dir = music_dir().join("Playlists");
+    assert_eq!(broken[&playlists_dir.join("moved.m3u")], [Track::new("Moved/old.mp3")]);
+    assert_eq!(broken[&playlists_dir.join("moved.m3u8")], [Track::new("Moved/old.mp3")]);
+    assert!(!broken.contains_key(&playlists_dir.join("unmoved.m3u")));
+}
+
+#[test]
+fn map_paths_extension_and_prefix() {
+    music_dir();
+    let path = fixture("Playlists/map-paths.m3u", "A/a.flac\nB/b.mp3\nA/a.flac\nA/a.mp3\n");
+
+    let mut pl = Playlist::open(&path).unwrap();
+    let mapping = pl.map_paths(|x| x.with_extension("mp3"));
+    assert_eq!(mapping, PathMapping { n_changed: 2, n_collisions: 1 });
+    assert_eq!(pl.tracks().map(|x| x.path.as_str()).collect::<Vec<_>>(), ["A/a.mp3", "B/b.mp3", "A/a.mp3", "A/a.mp3"]);
+    assert_eq!(pl.count_occurrences(&Track::new("A/a.mp3")), 3);
+    assert_integrity(&pl);
+
+    let mapping = pl.map_paths(|x| x.strip_prefix("A").map_or(x.to_owned(), |y| Utf8PathBuf::from("Z").join(y)));
+    assert_eq!(mapping, PathMapping { n_changed: 3, n_collisions: 0 });
+    assert_eq!(pl.tracks().map(|x| x.path.as_str()).collect::<Vec<_>>(), ["Z/a.mp3", "B/b.mp3", "Z/a.mp3", "Z/a.mp3"]);
+    assert!(!pl.contains(&Track::new("A/a.mp3")));
+    assert_integrity(&pl);
+}
+
+#[test]
+fn commented_playlist() {
+    music_dir();
+    let path = fixture("Playlists/commented.m3u", "#EXTM3U\n\n# Favourites\nA/a.mp3\n\n\n#B/b.mp3\nC/c.mp3\n\n");
+
+    let pl = Playlist::open(&path).unwrap();
+    assert_eq!(pl.tracks().map(|x| x.path.as_str()).collect::<Vec<_>>(), ["A/a.mp3", "C/c.mp3"]);
+    assert!(!pl.contains(&Track::new("#EXTM3U")));
+    assert!(!pl.contains(&Track::new("#B/b.mp3")));
+    assert!(!pl.contains(&Track::new("")));
+    assert_integrity(&pl);
+}
+
+#[test]
+fn seeded_shuffle_is_reproducible() {
+    music_dir();
+    let contents = (0..50).map(|i| format!("Shuffle/{}.mp3\n", i)).collect::<String>();
+    let path = fixture("Playlists/shuffle.m3u", &contents);
+    let shuffled = |seed| {
+        let mut pl = Playlist::open(&path).unwrap();
+        pl.shuffle(Some(seed));
+        assert_integrity(&pl);
+        pl.tracks().cloned().collect::<Vec<Track>>()
+    };
+
+    let original = Playlist::open(&path).unwrap().tracks().cloned().collect::<Vec<Track>>();
+    assert_eq!(shuffled(42), shuffled(42));
+    assert_ne!(shuffled(42), original);
+    assert_ne!(shuffled(42), shuffled(43));
+}
+
+#[test]
+fn noop_removal_keeps_unmodified() {
+    music_dir();
+    let path = fixture("Playlists/noop.m3u", "A/a.mp3\n");
+
+    let mut pl = Playlist::open(&path).unwrap();
+    assert_eq!(pl.remove_all(&Track::new("Z/z.mp3")), 0);
+    assert!(!pl.is_modified());
+}
+
+#[test]
+fn refresh_extinf_counts_skipped_tracks() {
+    audio_files(&["Refresh/tagless.mp3"]);
+    let path = fixture("Playlists/refresh.m3u", "Refresh/tagless.mp3\nRefresh/gone.mp3\nRefresh/gone.mp3\n");
+
+    let mut pl = Playlist::open(&path).unwrap();
+    let refresh = pl.refresh_extinf().unwrap();
+    assert_eq!(refresh, ExtInfRefresh { n_updated: 1, n_skipped: 2 });
+    assert_eq!(pl.extinf_at(0).unwrap().title, "tagless");
+    assert!(pl.extinf_at(1).is_none());
+}
+
+#[test]
+fn from_dir_extensions_and_symlinks() {
+    audio_files(&["FromDir/a.MP3", "FromDir/b.mp3", "FromDir/notes.txt", "FromDir/Sub/c.Flac"]);
+    std::os::unix::fs::symlink(music_dir().join("FromDir"), music_dir().join("FromDir/Sub/loop")).unwrap();
+
+    let pl = Playlist::from_dir("fromdir.m3u", music_dir().join("FromDir"), true, &["mp3", "flac"]).unwrap();
+    let tracks = pl.tracks().map(|x| x.path.as_str()).collect::<Vec<_>>();
+    assert_eq!(tracks, ["FromDir/a.MP3", "FromDir/b.mp3", "FromDir/Sub/c.Flac"]);
+}
+
+#[test]
+fn remove_at_before_later_duplicates() {
+    music_dir();
+    let path = fixture("Playlists/later-duplicates.m3u", "X/x.mp3\nA/a.mp3\nA/a.mp3\n");
+
+    let mut pl = Playlist::open(&path).unwrap();
+    pl.remove_at(0);
+    assert_eq!(pl.track_positions(&Track::new("A/a.mp3")), Some(&vec![0, 1]));
+    assert_integrity(&pl);
+}
+
+#[test]
+fn write_follows_symlinks_and_keeps_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    music_dir();
+    let real = fixture("Playlists/real.m3u", "A.mp3\n");
+    let link = music_dir().join("Playlists/link.m3u");
+    std::os::unix::fs::symlink(&real, &link).unwrap();
+    fs::set_permissions(&real, fs::Permissions::from_mode(0o600)).unwrap();
+
+    let mut pl = Playlist::open(&link).unwrap();
+    pl.add_track(Track::new("B.mp3"));
+    pl.write().unwrap();
+    assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+    assert_eq!(fs::read_to_string(&real).unwrap(), "A.mp3\nB.mp3\n");
+    assert_eq!(fs::metadata(&real).unwrap().permissions().mode() & 0o777, 0o600);
+}
+
+#[test]
+fn byte_order_mark_is_skipped() {
+    music_dir();
+    let path = fixture("Playlists/bom.m3u8", "\u{feff}#EXTM3U\n#EXTINF:1,X\nA/a.mp3\n");
+
+    let pl = Playlist::open(&path).unwrap();
+    assert_eq!(pl.tracks().collect::<Vec<_>>(), [&Track::new("A/a.mp3")]);
+    assert_eq!(pl.extinf_at(0).unwrap().title, "X");
+}
+
+#[test]
+fn rename_tracks_strict_and_lenient() {
+    music_dir();
+    let path = fixture("Playlists/rename.m3u", "A/a.mp3\nB/b.mp3\n");
+    let edits = [
+        (Track::new("A/a.mp3"), Utf8PathBuf::from("B/b.mp3")),
+        (Track::new("B/b.mp3"), Utf8PathBuf::from("A/a.mp3")),
+        (Track::new("Z/z.mp3"), Utf8PathBuf::from("Y/y.mp3")),
+    ].into_iter().collect::<HashMap<_, _>>();
+
+    let mut pl = Playlist::open(&path).unwrap();
+    assert!(matches!(pl.rename_tracks(&edits, true), Err(MusicToolsError::MissingTrack { .. })));
+    assert!(!pl.is_modified());
+    assert_eq!(pl.rename_tracks(&edits, false).unwrap(), 2);
+    assert_eq!(pl.tracks().map(|x| x.path.as_str()).collect::<Vec<_>>(), ["B/b.mp3", "A/a.mp3"]);
+    assert_integrity(&pl);
+}